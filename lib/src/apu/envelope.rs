@@ -0,0 +1,59 @@
+// volume envelope shared by channel 1, 2 and 4 (NRx2)
+#[derive(Debug)]
+pub struct Envelope {
+    initial_volume: u8,
+    increase: bool,
+    period: u8,
+
+    pub volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope {
+            initial_volume: 0,
+            increase: false,
+            period: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        (self.initial_volume << 4) | if self.increase { 0x08 } else { 0 } | self.period
+    }
+
+    pub fn write(&mut self, v: u8) {
+        self.initial_volume = (v & 0xf0) >> 4;
+        self.increase = v & 0x08 != 0;
+        self.period = v & 0x07;
+    }
+
+    // the DAC is powered while any of the upper 5 bits is set
+    pub fn dac_enabled(&self) -> bool {
+        self.read() & 0xf8 != 0
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+
+    // 64 Hz
+    pub fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return;
+        }
+        self.timer = self.period;
+        if self.increase && self.volume < 0x0f {
+            self.volume += 1;
+        } else if !self.increase && self.volume > 0 {
+            self.volume -= 1;
+        }
+    }
+}
@@ -1,13 +1,20 @@
+mod envelope;
 mod noise;
 mod square;
 mod wave;
 
 use bitflags::bitflags;
+use std::collections::VecDeque;
 
 use self::noise::Noise;
 use self::square::Square;
 use self::wave::Wave;
 
+const CPU_CLOCK: u64 = 4 * 1024 * 1024;
+// 512 Hz
+const FRAME_SEQUENCER_CLOCKS: u32 = 8192;
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
 #[derive(Debug)]
 pub struct APU {
     channel1: Square,
@@ -19,6 +26,14 @@ pub struct APU {
     right_volume: MasterVolume, // so1
     right_output: SelectChannels,
     enable: bool,
+
+    frame_clocks: u32,
+    frame_step: u8,
+    sample_rate: u32,
+    sample_phase: u64,
+    // interleaved stereo samples (left, right)
+    samples: VecDeque<i16>,
+    capacitor: (f32, f32),
 }
 
 #[derive(Debug)]
@@ -57,7 +72,147 @@ impl APU {
             right_volume: MasterVolume::new(),
             right_output: SelectChannels::empty(),
             enable: false,
+            frame_clocks: 0,
+            frame_step: 0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            sample_phase: 0,
+            samples: VecDeque::new(),
+            capacitor: (0.0, 0.0),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        self.sample_rate = rate.max(1);
+    }
+
+    // number of i16 values (2 per stereo frame) waiting to be drained
+    pub fn samples_len(&self) -> usize {
+        self.samples.len()
+    }
+
+    // fills `out` with interleaved stereo samples and returns how many were written
+    pub fn drain_samples(&mut self, out: &mut [i16]) -> usize {
+        let len = out.len().min(self.samples.len()) & !1;
+        for (o, s) in out.iter_mut().zip(self.samples.drain(..len)) {
+            *o = s;
         }
+        len
+    }
+
+    pub fn run(&mut self, ticks: u32) {
+        let mut ticks = ticks;
+        while ticks > 0 {
+            let rate = self.sample_rate as u64;
+            let until_sample = (CPU_CLOCK - self.sample_phase).div_ceil(rate) as u32;
+            let until_frame = FRAME_SEQUENCER_CLOCKS - self.frame_clocks;
+            let step = ticks.min(until_sample).min(until_frame);
+
+            if self.enable {
+                self.channel1.run(step);
+                self.channel2.run(step);
+                self.channel3.run(step);
+                self.channel4.run(step);
+            }
+
+            self.frame_clocks += step;
+            if self.frame_clocks >= FRAME_SEQUENCER_CLOCKS {
+                self.frame_clocks -= FRAME_SEQUENCER_CLOCKS;
+                if self.enable {
+                    self.step_frame_sequencer();
+                }
+            }
+
+            self.sample_phase += step as u64 * rate;
+            if self.sample_phase >= CPU_CLOCK {
+                self.sample_phase -= CPU_CLOCK;
+                self.push_sample();
+            }
+
+            ticks -= step;
+        }
+    }
+
+    fn step_frame_sequencer(&mut self) {
+        // length: 256 Hz, sweep: 128 Hz, envelope: 64 Hz
+        if self.frame_step & 0x01 == 0 {
+            self.channel1.clock_length();
+            self.channel2.clock_length();
+            self.channel3.clock_length();
+            self.channel4.clock_length();
+        }
+        if self.frame_step == 2 || self.frame_step == 6 {
+            self.channel1.clock_sweep();
+        }
+        if self.frame_step == 7 {
+            self.channel1.clock_envelope();
+            self.channel2.clock_envelope();
+            self.channel4.clock_envelope();
+        }
+        self.frame_step = (self.frame_step + 1) & 0x07;
+    }
+
+    // digital 0x0-0xf to analog -1.0..1.0, silent while the DAC is off
+    fn dac(enabled: bool, output: u8) -> f32 {
+        if !enabled {
+            return 0.0;
+        }
+        output as f32 / 7.5 - 1.0
+    }
+
+    fn mix(&self, select: SelectChannels, volume: &MasterVolume, outputs: &[f32; 4]) -> f32 {
+        let channels = [
+            SelectChannels::CHANNEL1,
+            SelectChannels::CHANNEL2,
+            SelectChannels::CHANNEL3,
+            SelectChannels::CHANNEL4,
+        ];
+        let sum: f32 = channels
+            .iter()
+            .zip(outputs.iter())
+            .filter(|(c, _)| select.contains(**c))
+            .map(|(_, o)| o)
+            .sum();
+        sum / 4.0 * (volume.level + 1) as f32 / 8.0
+    }
+
+    // removes the DC offset like the capacitor on the hardware output
+    fn high_pass(&self, capacitor: &mut f32, input: f32) -> f32 {
+        let factor = 0.999958_f32.powf(CPU_CLOCK as f32 / self.sample_rate as f32);
+        let output = input - *capacitor;
+        *capacitor = input - output * factor;
+        output
+    }
+
+    fn push_sample(&mut self) {
+        let (left, right) = if self.enable {
+            let outputs = [
+                APU::dac(self.channel1.dac_enabled(), self.channel1.output()),
+                APU::dac(self.channel2.dac_enabled(), self.channel2.output()),
+                APU::dac(self.channel3.dac_enabled(), self.channel3.output()),
+                APU::dac(self.channel4.dac_enabled(), self.channel4.output()),
+            ];
+            let left = self.mix(self.left_output, &self.left_volume, &outputs);
+            let right = self.mix(self.right_output, &self.right_volume, &outputs);
+
+            let mut capacitor = self.capacitor;
+            let left = self.high_pass(&mut capacitor.0, left);
+            let right = self.high_pass(&mut capacitor.1, right);
+            self.capacitor = capacitor;
+            (left, right)
+        } else {
+            (0.0, 0.0)
+        };
+
+        // keep at most 1 second when nobody drains
+        if self.samples.len() >= self.sample_rate as usize * 2 {
+            self.samples.drain(..2);
+        }
+        self.samples.push_back((left * i16::MAX as f32) as i16);
+        self.samples.push_back((right * i16::MAX as f32) as i16);
     }
 
     pub fn read_byte(&self, addr: u16) -> u8 {
@@ -76,7 +231,10 @@ impl APU {
 
             0xff1a => self.channel3.read_nr_x0(),
             0xff1b => self.channel3.read_nr_x1(),
-            0xff1c..=0xff1f => 0xff, // TODO
+            0xff1c => self.channel3.read_nr_x2(),
+            0xff1d => 0xff, // write only
+            0xff1e => self.channel3.read_nr_x4(),
+            0xff1f => 0xff, // unused
 
             0xff20 => self.channel4.read_nr_x1(),
             0xff21 => self.channel4.read_nr_x2(),
@@ -91,18 +249,23 @@ impl APU {
             }
             0xff25 => (self.left_output.bits << 4) | self.right_output.bits,
             0xff26 => {
-                (if self.enable { 0x80 } else { 0 })
+                0x70 | (if self.enable { 0x80 } else { 0 })
                     | (if self.channel4.status { 0b_1000 } else { 0 })
                     | (if self.channel3.status { 0b_0100 } else { 0 })
                     | (if self.channel2.status { 0b_0010 } else { 0 })
                     | (if self.channel1.status { 0b_0001 } else { 0 })
             }
-            0xff27..=0xff3f => 0xff, // TODO
+            0xff27..=0xff2f => 0xff, // unused
+            0xff30..=0xff3f => self.channel3.read_ram(addr),
             _ => unimplemented!("read: Sound I/O {:04x}", addr),
         }
     }
 
     pub fn write_byte(&mut self, addr: u16, v: u8) {
+        if !self.enable && (0xff10..=0xff25).contains(&addr) {
+            // registers are read only while powered off
+            return;
+        }
         match addr {
             0xff10 => self.channel1.write_nr_x0(v),
             0xff11 => self.channel1.write_nr_x1(v),
@@ -118,7 +281,10 @@ impl APU {
 
             0xff1a => self.channel3.write_nr_x0(v),
             0xff1b => self.channel3.write_nr_x1(v),
-            0xff1c..=0xff1f => (), // TODO
+            0xff1c => self.channel3.write_nr_x2(v),
+            0xff1d => self.channel3.write_nr_x3(v),
+            0xff1e => self.channel3.write_nr_x4(v),
+            0xff1f => (), // unused
 
             0xff20 => self.channel4.write_nr_x1(v),
             0xff21 => self.channel4.write_nr_x2(v),
@@ -136,17 +302,25 @@ impl APU {
                 self.right_output = SelectChannels::from_bits_truncate(v);
             }
             0xff26 => {
-                self.enable = v & 0x80 != 0;
-                if !self.enable {
-                    self.channel4.status = v & 0b_1000 != 0;
-                    self.channel3.status = v & 0b_0100 != 0;
-                    self.channel2.status = v & 0b_0010 != 0;
-                    self.channel1.status = v & 0b_0001 != 0;
+                let enable = v & 0x80 != 0;
+                if self.enable && !enable {
+                    // powering off clears all sound registers
+                    self.channel1 = Square::new();
+                    self.channel2 = Square::new();
+                    self.channel3.reset();
+                    self.channel4 = Noise::new();
+                    self.left_volume = MasterVolume::new();
+                    self.right_volume = MasterVolume::new();
                     self.right_output = SelectChannels::empty();
                     self.left_output = SelectChannels::empty();
                 }
+                if !self.enable && enable {
+                    self.frame_step = 0;
+                }
+                self.enable = enable;
             }
-            0xff27..=0xff3f => (), // TODO
+            0xff27..=0xff2f => (), // unused
+            0xff30..=0xff3f => self.channel3.write_ram(addr, v),
             _ => unimplemented!("write: Sound I/O {:04x} {:02x}", addr, v),
         }
     }
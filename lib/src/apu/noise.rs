@@ -1,5 +1,9 @@
 use std::fmt;
 
+use super::envelope::Envelope;
+
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
 pub struct Noise {
     // NR44
     pub status: bool,
@@ -11,12 +15,13 @@ pub struct Noise {
     polynomial_ratio: u8,
 
     // NR42
-    volume: u8,
-    increase: bool,
-    length: u8,
+    envelope: Envelope,
 
     // NR41
-    sound_length: u8,
+    length_counter: u16,
+
+    timer: u32,
+    lfsr: u16,
 }
 
 impl Noise {
@@ -24,32 +29,33 @@ impl Noise {
         Noise {
             status: false,
             use_length: false,
-            volume: 0,
-            increase: false,
-            length: 0,
-            sound_length: 0,
+            envelope: Envelope::new(),
+            length_counter: 0,
             polynomial_frequency: 0,
             polynomial_width: false,
             polynomial_ratio: 0,
+            timer: 0,
+            lfsr: 0x7fff,
         }
     }
 
     pub fn read_nr_x1(&self) -> u8 {
-        self.sound_length
+        0xff
     }
 
     pub fn write_nr_x1(&mut self, v: u8) {
-        self.sound_length = v & 0x3f;
+        self.length_counter = 64 - (v & 0x3f) as u16;
     }
 
     pub fn read_nr_x2(&self) -> u8 {
-        (self.volume << 4) | if self.increase { 0x08 } else { 0 } | self.length
+        self.envelope.read()
     }
 
     pub fn write_nr_x2(&mut self, v: u8) {
-        self.volume = (v & 0xf0) >> 4;
-        self.increase = v & 0x08 != 0;
-        self.length = v & 0x07;
+        self.envelope.write(v);
+        if !self.envelope.dac_enabled() {
+            self.status = false;
+        }
     }
 
     pub fn read_nr_x3(&self) -> u8 {
@@ -69,8 +75,67 @@ impl Noise {
     }
 
     pub fn write_nr_x4(&mut self, v: u8) {
-        self.status = v & 0x80 != 0;
         self.use_length = v & 0x40 != 0;
+        if v & 0x80 != 0 {
+            self.trigger();
+        }
+    }
+
+    fn period(&self) -> u32 {
+        DIVISORS[self.polynomial_ratio as usize] << self.polynomial_frequency
+    }
+
+    fn trigger(&mut self) {
+        self.status = self.envelope.dac_enabled();
+        if self.length_counter == 0 {
+            self.length_counter = 64;
+        }
+        self.timer = self.period();
+        self.envelope.trigger();
+        self.lfsr = 0x7fff;
+    }
+
+    pub fn run(&mut self, ticks: u32) {
+        let mut ticks = ticks;
+        while ticks >= self.timer {
+            ticks -= self.timer;
+            self.timer = self.period();
+
+            let xor = (self.lfsr & 0x01) ^ ((self.lfsr >> 1) & 0x01);
+            self.lfsr = (self.lfsr >> 1) | (xor << 14);
+            if self.polynomial_width {
+                // 7 bit mode
+                self.lfsr = (self.lfsr & !0x40) | (xor << 6);
+            }
+        }
+        self.timer -= ticks;
+    }
+
+    // 256 Hz
+    pub fn clock_length(&mut self) {
+        if self.use_length && self.length_counter > 0 {
+            self.length_counter -= 1;
+            if self.length_counter == 0 {
+                self.status = false;
+            }
+        }
+    }
+
+    // 64 Hz
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    // 0x0-0xf
+    pub fn output(&self) -> u8 {
+        if !self.status || self.lfsr & 0x01 != 0 {
+            return 0;
+        }
+        self.envelope.volume
     }
 }
 
@@ -78,8 +143,10 @@ impl fmt::Debug for Noise {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "Noise: {{ 1: {:02x}, 2: {:02x}, 3: {:02x} }}",
-            self.sound_length, 0, 0
+            "Noise: {{ 2: {:02x}, 3: {:02x}, 4: {:02x} }}",
+            self.read_nr_x2(),
+            self.read_nr_x3(),
+            self.read_nr_x4()
         )
     }
 }
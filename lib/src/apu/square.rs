@@ -1,5 +1,14 @@
 use std::fmt;
 
+use super::envelope::Envelope;
+
+const DUTY_PATTERNS: [u8; 4] = [
+    0b_0000_0001, // 12.5%
+    0b_1000_0001, // 25%
+    0b_1000_0111, // 50%
+    0b_0111_1110, // 75%
+];
+
 pub struct Square {
     // NR13-14/NR23-24
     pub status: bool,
@@ -7,18 +16,22 @@ pub struct Square {
     frequency: u16,
 
     // NR12/NR22
-    volume: u8,
-    increase: bool,
-    length: u8,
+    envelope: Envelope,
 
     // NR11/NR21
     wave_duty: u8,
-    sound_length: u8,
+    length_counter: u16,
 
-    // NR0
+    // NR10
     sweep_time: u8,
     sweep_increase: bool,
     sweep_shift: u8,
+
+    timer: u32,
+    duty_position: u8,
+    sweep_enable: bool,
+    sweep_timer: u8,
+    shadow_frequency: u16,
 }
 
 impl Square {
@@ -27,19 +40,24 @@ impl Square {
             status: false,
             use_length: false,
             frequency: 0,
-            volume: 0,
-            increase: false,
-            length: 0,
+            envelope: Envelope::new(),
             wave_duty: 0,
-            sound_length: 0,
+            length_counter: 0,
             sweep_time: 0,
             sweep_increase: false,
             sweep_shift: 0,
+            timer: 0,
+            duty_position: 0,
+            sweep_enable: false,
+            sweep_timer: 0,
+            shadow_frequency: 0,
         }
     }
 
     pub fn read_nr_x0(&self) -> u8 {
-        (self.sweep_time << 4) | if self.sweep_increase { 0 } else { 0x08 } | self.sweep_shift
+        0x80 | (self.sweep_time << 4)
+            | if self.sweep_increase { 0 } else { 0x08 }
+            | self.sweep_shift
     }
 
     pub fn write_nr_x0(&mut self, v: u8) {
@@ -54,17 +72,18 @@ impl Square {
 
     pub fn write_nr_x1(&mut self, v: u8) {
         self.wave_duty = (v & 0xc0) >> 6;
-        self.sound_length = v & 0x3f;
+        self.length_counter = 64 - (v & 0x3f) as u16;
     }
 
     pub fn read_nr_x2(&self) -> u8 {
-        (self.volume << 4) | if self.increase { 0x08 } else { 0 } | self.length
+        self.envelope.read()
     }
 
     pub fn write_nr_x2(&mut self, v: u8) {
-        self.volume = (v & 0xf0) >> 4;
-        self.increase = v & 0x08 != 0;
-        self.length = v & 0x07;
+        self.envelope.write(v);
+        if !self.envelope.dac_enabled() {
+            self.status = false;
+        }
     }
 
     pub fn write_nr_x3(&mut self, v: u8) {
@@ -76,9 +95,112 @@ impl Square {
     }
 
     pub fn write_nr_x4(&mut self, v: u8) {
-        self.status = v & 0x80 != 0;
         self.use_length = v & 0x40 != 0;
-        self.frequency = (self.frequency & 0x00ff) | ((v as u16) << 8);
+        self.frequency = (self.frequency & 0x00ff) | (((v & 0x07) as u16) << 8);
+        if v & 0x80 != 0 {
+            self.trigger();
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 4
+    }
+
+    fn trigger(&mut self) {
+        self.status = self.envelope.dac_enabled();
+        if self.length_counter == 0 {
+            self.length_counter = 64;
+        }
+        self.timer = self.period();
+        self.envelope.trigger();
+
+        self.shadow_frequency = self.frequency;
+        self.sweep_timer = if self.sweep_time == 0 {
+            8
+        } else {
+            self.sweep_time
+        };
+        self.sweep_enable = self.sweep_time != 0 || self.sweep_shift != 0;
+        if self.sweep_shift != 0 {
+            self.calculate_sweep();
+        }
+    }
+
+    fn calculate_sweep(&mut self) -> u16 {
+        let delta = self.shadow_frequency >> self.sweep_shift;
+        let frequency = if self.sweep_increase {
+            self.shadow_frequency + delta
+        } else {
+            self.shadow_frequency.wrapping_sub(delta)
+        };
+        if frequency > 0x07ff {
+            self.status = false;
+        }
+        frequency
+    }
+
+    pub fn run(&mut self, ticks: u32) {
+        let mut ticks = ticks;
+        while ticks >= self.timer {
+            ticks -= self.timer;
+            self.timer = self.period();
+            self.duty_position = (self.duty_position + 1) & 0x07;
+        }
+        self.timer -= ticks;
+    }
+
+    // 256 Hz
+    pub fn clock_length(&mut self) {
+        if self.use_length && self.length_counter > 0 {
+            self.length_counter -= 1;
+            if self.length_counter == 0 {
+                self.status = false;
+            }
+        }
+    }
+
+    // 64 Hz
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    // 128 Hz
+    pub fn clock_sweep(&mut self) {
+        self.sweep_timer = self.sweep_timer.saturating_sub(1);
+        if self.sweep_timer > 0 {
+            return;
+        }
+        self.sweep_timer = if self.sweep_time == 0 {
+            8
+        } else {
+            self.sweep_time
+        };
+        if !self.sweep_enable || self.sweep_time == 0 {
+            return;
+        }
+
+        let frequency = self.calculate_sweep();
+        if frequency <= 0x07ff && self.sweep_shift != 0 {
+            self.frequency = frequency;
+            self.shadow_frequency = frequency;
+            self.calculate_sweep();
+        }
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    // 0x0-0xf
+    pub fn output(&self) -> u8 {
+        if !self.status {
+            return 0;
+        }
+        if DUTY_PATTERNS[self.wave_duty as usize] & (0x80 >> self.duty_position) != 0 {
+            self.envelope.volume
+        } else {
+            0
+        }
     }
 }
 
@@ -87,10 +209,10 @@ impl fmt::Debug for Square {
         write!(
             f,
             "Square {{ 0: {:02x}, 1: {:02x}, 2: {:02x}, 4: {:02x} }}",
-            (self.sweep_time << 4) | if self.sweep_increase { 0 } else { 0x08 } | self.sweep_shift,
-            (self.wave_duty << 6) | 0x3f,
-            (self.volume << 4) | if self.increase { 0x08 } else { 0 } | self.length,
-            0b_1011_1111 | if self.use_length { 0x40 } else { 0 }
+            self.read_nr_x0(),
+            self.read_nr_x1(),
+            self.read_nr_x2(),
+            self.read_nr_x4()
         )
     }
 }
@@ -1,36 +1,179 @@
 use std::fmt;
 
+pub const WAVE_RAM_SIZE: usize = 16;
+
 pub struct Wave {
     pub status: bool,
-    length: u8,
+
+    // NR30
+    dac_enable: bool,
+
+    // NR31
+    length_counter: u16,
+
+    // NR32
+    output_level: u8,
+
+    // NR33-34
+    use_length: bool,
+    frequency: u16,
+
+    // 0xff30-0xff3f: 32 4-bit samples
+    ram: [u8; WAVE_RAM_SIZE],
+
+    timer: u32,
+    position: u8,
+    // clocks since the channel read a sample from the wave RAM
+    fetched_clocks: u32,
 }
 
 impl Wave {
     pub fn new() -> Wave {
         Wave {
             status: false,
-            length: 0,
+            dac_enable: false,
+            length_counter: 0,
+            output_level: 0,
+            use_length: false,
+            frequency: 0,
+            ram: [0; WAVE_RAM_SIZE],
+            timer: 0,
+            position: 0,
+            fetched_clocks: 0,
         }
     }
 
+    // powering off the APU clears the registers but not the wave RAM
+    pub fn reset(&mut self) {
+        *self = Wave {
+            ram: self.ram,
+            ..Wave::new()
+        };
+    }
+
     pub fn read_nr_x0(&self) -> u8 {
-        if self.status {
-            0x80
-        } else {
-            0x00
-        }
+        0x7f | if self.dac_enable { 0x80 } else { 0x00 }
     }
 
     pub fn write_nr_x0(&mut self, v: u8) {
-        self.status = v & 0x80 != 0;
+        self.dac_enable = v & 0x80 != 0;
+        if !self.dac_enable {
+            self.status = false;
+        }
     }
 
     pub fn read_nr_x1(&self) -> u8 {
-        self.length
+        0xff
     }
 
     pub fn write_nr_x1(&mut self, v: u8) {
-        self.length = v;
+        self.length_counter = 256 - v as u16;
+    }
+
+    pub fn read_nr_x2(&self) -> u8 {
+        0x9f | (self.output_level << 5)
+    }
+
+    pub fn write_nr_x2(&mut self, v: u8) {
+        self.output_level = (v & 0x60) >> 5;
+    }
+
+    pub fn write_nr_x3(&mut self, v: u8) {
+        self.frequency = (self.frequency & 0x0700) | (v as u16);
+    }
+
+    pub fn read_nr_x4(&self) -> u8 {
+        0b_1011_1111 | if self.use_length { 0x40 } else { 0 }
+    }
+
+    pub fn write_nr_x4(&mut self, v: u8) {
+        self.use_length = v & 0x40 != 0;
+        self.frequency = (self.frequency & 0x00ff) | (((v & 0x07) as u16) << 8);
+        if v & 0x80 != 0 {
+            self.trigger();
+        }
+    }
+
+    // while playing, the CPU can only reach the byte the channel is reading,
+    // and only right when it is read (DMG)
+    fn ram_index(&self, addr: u16) -> Option<usize> {
+        if !self.status {
+            return Some((addr & 0x0f) as usize);
+        }
+        if self.fetched_clocks < 2 {
+            Some((self.position >> 1) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn read_ram(&self, addr: u16) -> u8 {
+        match self.ram_index(addr) {
+            Some(i) => self.ram[i],
+            None => 0xff,
+        }
+    }
+
+    pub fn write_ram(&mut self, addr: u16, v: u8) {
+        if let Some(i) = self.ram_index(addr) {
+            self.ram[i] = v;
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 2
+    }
+
+    fn trigger(&mut self) {
+        self.status = self.dac_enable;
+        if self.length_counter == 0 {
+            self.length_counter = 256;
+        }
+        self.timer = self.period();
+        self.position = 0;
+    }
+
+    pub fn run(&mut self, ticks: u32) {
+        let mut ticks = ticks;
+        self.fetched_clocks = self.fetched_clocks.saturating_add(ticks);
+        while ticks >= self.timer {
+            ticks -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) & 0x1f;
+            self.fetched_clocks = ticks;
+        }
+        self.timer -= ticks;
+    }
+
+    // 256 Hz
+    pub fn clock_length(&mut self) {
+        if self.use_length && self.length_counter > 0 {
+            self.length_counter -= 1;
+            if self.length_counter == 0 {
+                self.status = false;
+            }
+        }
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.dac_enable
+    }
+
+    // 0x0-0xf
+    pub fn output(&self) -> u8 {
+        if !self.status {
+            return 0;
+        }
+        let sample = self.ram[(self.position >> 1) as usize];
+        let sample = if self.position & 0x01 == 0 {
+            sample >> 4
+        } else {
+            sample & 0x0f
+        };
+        match self.output_level {
+            0 => 0,
+            n => sample >> (n - 1),
+        }
     }
 }
 
@@ -38,11 +181,11 @@ impl fmt::Debug for Wave {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "Wave: {{ 0: {:02x}, 1: {:02x}, 2: {:02x}, 3: {:02x} }}",
-            if self.status { 0x80 } else { 0 },
-            self.length,
-            0,
-            0
+            "Wave: {{ 0: {:02x}, 2: {:02x}, 4: {:02x}, ram: {:02x?} }}",
+            self.read_nr_x0(),
+            self.read_nr_x2(),
+            self.read_nr_x4(),
+            self.ram
         )
     }
 }
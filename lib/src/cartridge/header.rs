@@ -0,0 +1,175 @@
+use std::error;
+use std::fmt;
+
+use super::ROM_BANK_SIZE;
+
+const HEADER_END: usize = 0x0150;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub manufacturer_code: String,
+    pub cgb_flag: u8,
+    pub new_licensee_code: String,
+    pub sgb_flag: u8,
+    pub cartridge_type: u8,
+    // in bytes
+    pub rom_size: usize,
+    pub ram_size: usize,
+    pub destination_code: u8,
+    pub old_licensee_code: u8,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    computed_header_checksum: u8,
+    computed_global_checksum: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CartridgeError {
+    TooSmall(usize),
+    InvalidRomSize(u8),
+    InvalidRamSize(u8),
+    UnsupportedType(u8),
+    RomSizeMismatch { expected: usize, actual: usize },
+    HeaderChecksum { expected: u8, actual: u8 },
+    GlobalChecksum { expected: u16, actual: u16 },
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::TooSmall(size) => {
+                write!(f, "ROM is too small to have a header: {} bytes", size)
+            }
+            CartridgeError::InvalidRomSize(v) => write!(f, "ROM size is invalid: {:02x}", v),
+            CartridgeError::InvalidRamSize(v) => write!(f, "RAM size is invalid: {:02x}", v),
+            CartridgeError::UnsupportedType(v) => {
+                write!(f, "cartridge type is not supported: {:02x}", v)
+            }
+            CartridgeError::RomSizeMismatch { expected, actual } => write!(
+                f,
+                "ROM size does not match the header: expected {} bytes, actual {} bytes",
+                expected, actual
+            ),
+            CartridgeError::HeaderChecksum { expected, actual } => write!(
+                f,
+                "header checksum mismatch: expected {:02x}, actual {:02x}",
+                expected, actual
+            ),
+            CartridgeError::GlobalChecksum { expected, actual } => write!(
+                f,
+                "global checksum mismatch: expected {:04x}, actual {:04x}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl error::Error for CartridgeError {}
+
+fn ascii(data: &[u8]) -> String {
+    data.iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as char)
+        .collect::<String>()
+}
+
+impl CartridgeHeader {
+    pub fn new(rom: &[u8]) -> Result<CartridgeHeader, CartridgeError> {
+        if rom.len() < HEADER_END {
+            return Err(CartridgeError::TooSmall(rom.len()));
+        }
+
+        let rom_banks: usize = match rom[0x0148] {
+            0 => 2,
+            1 => 4,
+            2 => 8,
+            3 => 16,
+            4 => 32,
+            5 => 64,
+            6 => 128,
+            7 => 256,
+            8 => 512,
+            0x52 => 72,
+            0x53 => 80,
+            0x54 => 96,
+            v => return Err(CartridgeError::InvalidRomSize(v)),
+        };
+        let ram_size: usize = match rom[0x0149] {
+            0 => 0,
+            1 => 2 * 1024,
+            2 => 8 * 1024,
+            3 => 32 * 1024,
+            4 => 128 * 1024,
+            5 => 64 * 1024,
+            v => return Err(CartridgeError::InvalidRamSize(v)),
+        };
+
+        let computed_header_checksum = rom[0x0134..=0x014c]
+            .iter()
+            .fold(0u8, |x, &v| x.wrapping_sub(v).wrapping_sub(1));
+        let computed_global_checksum = rom
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != 0x014e && i != 0x014f)
+            .fold(0u16, |x, (_, &v)| x.wrapping_add(v as u16));
+
+        let mut header = CartridgeHeader {
+            title: String::new(),
+            manufacturer_code: String::new(),
+            cgb_flag: rom[0x0143],
+            new_licensee_code: ascii(&rom[0x0144..0x0146]),
+            sgb_flag: rom[0x0146],
+            cartridge_type: rom[0x0147],
+            rom_size: rom_banks * ROM_BANK_SIZE,
+            ram_size,
+            destination_code: rom[0x014a],
+            old_licensee_code: rom[0x014b],
+            version: rom[0x014c],
+            header_checksum: rom[0x014d],
+            global_checksum: (rom[0x014e] as u16) << 8 | rom[0x014f] as u16,
+            computed_header_checksum,
+            computed_global_checksum,
+        };
+        // CGB titles use 0x013f-0x0142 for the manufacturer code
+        if header.is_cgb() {
+            header.title = ascii(&rom[0x0134..0x013f]);
+            header.manufacturer_code = ascii(&rom[0x013f..0x0143]);
+        } else {
+            header.title = ascii(&rom[0x0134..0x0144]);
+        }
+        Ok(header)
+    }
+
+    // CGB only (0xc0) or CGB enhanced (0x80)
+    pub fn is_cgb(&self) -> bool {
+        matches!(self.cgb_flag, 0x80 | 0xc0)
+    }
+
+    // the boot ROM refuses to start on a bad header checksum; the global checksum is never verified by hardware
+    pub fn verify(&self) -> Result<(), CartridgeError> {
+        if self.header_checksum != self.computed_header_checksum {
+            return Err(CartridgeError::HeaderChecksum {
+                expected: self.header_checksum,
+                actual: self.computed_header_checksum,
+            });
+        }
+        if self.global_checksum != self.computed_global_checksum {
+            return Err(CartridgeError::GlobalChecksum {
+                expected: self.global_checksum,
+                actual: self.computed_global_checksum,
+            });
+        }
+        Ok(())
+    }
+
+    // 0x33 means the new licensee code is used instead
+    pub fn licensee(&self) -> String {
+        if self.old_licensee_code == 0x33 {
+            self.new_licensee_code.clone()
+        } else {
+            format!("{:02X}", self.old_licensee_code)
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use super::{MemoryBankController, RAM_BANK_SIZE, ROM_BANK_SIZE};
+
+#[derive(Debug)]
+pub struct MBC1 {
+    rom_banks: usize,
+    rom_bank_number_hi: u8,
+    rom_bank_number_lo: u8,
+    ram_enable: bool,
+    mode: bool,
+}
+
+impl MBC1 {
+    pub fn new(rom_banks: usize) -> MBC1 {
+        MBC1 {
+            rom_banks,
+            rom_bank_number_hi: 0,
+            rom_bank_number_lo: 0,
+            ram_enable: false,
+            mode: false,
+        }
+    }
+
+    fn rom_offset(&self) -> usize {
+        let bank_number = if self.mode {
+            self.rom_bank_number_lo
+        } else {
+            self.rom_bank_number_hi << 5 | self.rom_bank_number_lo
+        };
+        let bank_number = match bank_number {
+            0x00 | 0x20 | 0x40 | 0x60 => bank_number + 1,
+            _ => bank_number,
+        };
+        ROM_BANK_SIZE * (bank_number as usize % self.rom_banks)
+    }
+
+    fn ram_offset(&self) -> usize {
+        if self.mode {
+            RAM_BANK_SIZE * (self.rom_bank_number_hi as usize)
+        } else {
+            0
+        }
+    }
+
+    fn ram_index(&self, ram: &[u8], addr: u16) -> Option<usize> {
+        if !self.ram_enable || ram.is_empty() {
+            return None;
+        }
+        Some(((addr & 0x1fff) as usize + self.ram_offset()) % ram.len())
+    }
+}
+
+impl MemoryBankController for MBC1 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            // ROM bank 00
+            0x0000..=0x3fff => rom[addr as usize],
+            // ROM bank 01-7f
+            _ => rom[(addr & 0x3fff) as usize + self.rom_offset()],
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, v: u8) {
+        match addr {
+            // RAM enable
+            0x0000..=0x1fff => self.ram_enable = v & 0x0f == 0x0a,
+            // ROM bank number(lower 5 bit)
+            0x2000..=0x3fff => self.rom_bank_number_lo = v & 0x1f,
+            // RAM bank number or ROM bank number(higher 2 bit)
+            0x4000..=0x5fff => self.rom_bank_number_hi = v & 0x03,
+            // ROM/RAM mode (0=ROM, 1=RAM)
+            _ => self.mode = v & 0x01 != 0,
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        match self.ram_index(ram, addr) {
+            Some(i) => ram[i],
+            None => 0xff,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, v: u8) {
+        if let Some(i) = self.ram_index(ram, addr) {
+            ram[i] = v;
+        }
+    }
+}
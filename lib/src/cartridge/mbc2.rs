@@ -0,0 +1,68 @@
+use super::{MemoryBankController, ROM_BANK_SIZE};
+
+// built-in 512x4 bit RAM
+pub const RAM_SIZE: usize = 512;
+
+#[derive(Debug)]
+pub struct MBC2 {
+    rom_banks: usize,
+    rom_bank_number: u8,
+    ram_enable: bool,
+}
+
+impl MBC2 {
+    pub fn new(rom_banks: usize) -> MBC2 {
+        MBC2 {
+            rom_banks,
+            rom_bank_number: 1,
+            ram_enable: false,
+        }
+    }
+
+    fn rom_offset(&self) -> usize {
+        ROM_BANK_SIZE * (self.rom_bank_number as usize % self.rom_banks)
+    }
+}
+
+impl MemoryBankController for MBC2 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            // ROM bank 00
+            0x0000..=0x3fff => rom[addr as usize],
+            // ROM bank 01-0f
+            _ => rom[(addr & 0x3fff) as usize + self.rom_offset()],
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, v: u8) {
+        if addr > 0x3fff {
+            return;
+        }
+        // address bit 8 selects the register
+        if addr & 0x0100 == 0 {
+            // RAM enable
+            self.ram_enable = v & 0x0f == 0x0a;
+        } else {
+            // ROM bank number
+            self.rom_bank_number = match v & 0x0f {
+                0 => 1,
+                n => n,
+            };
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enable {
+            return 0xff;
+        }
+        // only the lower 4 bits are connected
+        0xf0 | ram[(addr as usize) & (RAM_SIZE - 1)]
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, v: u8) {
+        if !self.ram_enable {
+            return;
+        }
+        ram[(addr as usize) & (RAM_SIZE - 1)] = v & 0x0f;
+    }
+}
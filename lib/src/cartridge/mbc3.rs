@@ -0,0 +1,280 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{MemoryBankController, RAM_BANK_SIZE, ROM_BANK_SIZE};
+
+// the RTC counts with the emulated clock, not the wall-clock
+const CPU_CLOCKS_PER_SECOND: u32 = 4 * 1024 * 1024;
+
+#[derive(Debug)]
+pub struct MBC3 {
+    rom_banks: usize,
+    rom_bank_number: u8,
+    // 0x00-0x03: RAM bank, 0x08-0x0c: RTC register
+    ram_bank_number: u8,
+    ram_enable: bool,
+    rtc: Option<Rtc>,
+    latch: u8,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Rtc {
+    clocks: u32,
+    registers: RtcRegisters,
+    latched: RtcRegisters,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct RtcRegisters {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16,
+    halt: bool,
+    carry: bool,
+}
+
+impl RtcRegisters {
+    fn read_byte(&self, select: u8) -> u8 {
+        match select {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0a => self.hours,
+            0x0b => self.days as u8,
+            0x0c => {
+                (if self.carry { 0x80 } else { 0 })
+                    | (if self.halt { 0x40 } else { 0 })
+                    | ((self.days >> 8) as u8 & 0x01)
+            }
+            _ => 0xff,
+        }
+    }
+
+    fn write_byte(&mut self, select: u8, v: u8) {
+        match select {
+            0x08 => self.seconds = v & 0x3f,
+            0x09 => self.minutes = v & 0x3f,
+            0x0a => self.hours = v & 0x1f,
+            0x0b => self.days = (self.days & 0x0100) | v as u16,
+            0x0c => {
+                self.days = (self.days & 0x00ff) | ((v as u16 & 0x01) << 8);
+                self.halt = v & 0x40 != 0;
+                self.carry = v & 0x80 != 0;
+            }
+            _ => (),
+        }
+    }
+
+    fn tick(&mut self) {
+        // out-of-range values count up to the register width before wrapping
+        self.seconds = (self.seconds + 1) & 0x3f;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+
+        self.minutes = (self.minutes + 1) & 0x3f;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+
+        self.hours = (self.hours + 1) & 0x1f;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+
+        self.days += 1;
+        if self.days > 0x01ff {
+            self.days = 0;
+            self.carry = true;
+        }
+    }
+
+    fn advance(&mut self, mut seconds: u64) {
+        // one second at a time while a counter is out of range, as those don't carry normally
+        while seconds > 0 && (self.seconds >= 60 || self.minutes >= 60 || self.hours >= 24) {
+            self.tick();
+            seconds -= 1;
+        }
+        let total = seconds
+            + self.seconds as u64
+            + self.minutes as u64 * 60
+            + self.hours as u64 * 3600
+            + self.days as u64 * 86400;
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        let days = total / 86400;
+        if days > 0x01ff {
+            self.carry = true;
+        }
+        self.days = (days & 0x01ff) as u16;
+    }
+}
+
+impl Rtc {
+    fn run(&mut self, ticks: u32) {
+        if self.registers.halt {
+            return;
+        }
+        self.clocks += ticks;
+        while self.clocks >= CPU_CLOCKS_PER_SECOND {
+            self.clocks -= CPU_CLOCKS_PER_SECOND;
+            self.registers.tick();
+        }
+    }
+
+    fn write_byte(&mut self, select: u8, v: u8) {
+        if select == 0x08 {
+            // writing seconds resets the sub-second counter
+            self.clocks = 0;
+        }
+        self.registers.write_byte(select, v);
+    }
+}
+
+impl MBC3 {
+    pub fn new(rom_banks: usize, timer: bool) -> MBC3 {
+        MBC3 {
+            rom_banks,
+            rom_bank_number: 1,
+            ram_bank_number: 0,
+            ram_enable: false,
+            rtc: if timer { Some(Rtc::default()) } else { None },
+            latch: 0xff,
+        }
+    }
+
+    fn rom_offset(&self) -> usize {
+        ROM_BANK_SIZE * (self.rom_bank_number as usize % self.rom_banks)
+    }
+
+    fn ram_index(&self, ram: &[u8], addr: u16) -> Option<usize> {
+        if ram.is_empty() {
+            return None;
+        }
+        let offset = RAM_BANK_SIZE * self.ram_bank_number as usize;
+        Some(((addr & 0x1fff) as usize + offset) % ram.len())
+    }
+}
+
+impl MemoryBankController for MBC3 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            // ROM bank 00
+            0x0000..=0x3fff => rom[addr as usize],
+            // ROM bank 01-7f
+            _ => rom[(addr & 0x3fff) as usize + self.rom_offset()],
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, v: u8) {
+        match addr {
+            // RAM and RTC enable
+            0x0000..=0x1fff => self.ram_enable = v & 0x0f == 0x0a,
+            // ROM bank number
+            0x2000..=0x3fff => {
+                self.rom_bank_number = match v & 0x7f {
+                    0 => 1,
+                    n => n,
+                }
+            }
+            // RAM bank number or RTC register select
+            0x4000..=0x5fff => self.ram_bank_number = v,
+            // latch clock data
+            _ => {
+                if let Some(rtc) = self.rtc.as_mut() {
+                    if self.latch == 0x00 && v == 0x01 {
+                        rtc.latched = rtc.registers;
+                    }
+                }
+                self.latch = v;
+            }
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enable {
+            return 0xff;
+        }
+        match self.ram_bank_number {
+            0x00..=0x03 => match self.ram_index(ram, addr) {
+                Some(i) => ram[i],
+                None => 0xff,
+            },
+            0x08..=0x0c => match self.rtc {
+                Some(rtc) => rtc.latched.read_byte(self.ram_bank_number),
+                None => 0xff,
+            },
+            _ => 0xff,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, v: u8) {
+        if !self.ram_enable {
+            return;
+        }
+        match self.ram_bank_number {
+            0x00..=0x03 => {
+                if let Some(i) = self.ram_index(ram, addr) {
+                    ram[i] = v;
+                }
+            }
+            0x08..=0x0c => {
+                if let Some(rtc) = self.rtc.as_mut() {
+                    rtc.write_byte(self.ram_bank_number, v);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn run(&mut self, ticks: u32) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.run(ticks);
+        }
+    }
+
+    // 48 bytes footer compatible with BGB/VBA:
+    // live S/M/H/DL/DH, latched S/M/H/DL/DH (u32 LE each), UNIX timestamp (u64 LE)
+    fn save_rtc(&self) -> Option<Vec<u8>> {
+        let rtc = self.rtc.as_ref()?;
+        let mut data = Vec::with_capacity(48);
+        for registers in [&rtc.registers, &rtc.latched] {
+            for select in 0x08..=0x0c {
+                data.extend(&(registers.read_byte(select) as u32).to_le_bytes());
+            }
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        data.extend(&timestamp.to_le_bytes());
+        Some(data)
+    }
+
+    // 44 bytes (32 bit timestamp) or 48 bytes (64 bit timestamp)
+    fn load_rtc(&mut self, data: &[u8]) -> Option<u64> {
+        let rtc = self.rtc.as_mut()?;
+        let timestamp = match data.len() {
+            48.. => u64::from_le_bytes(data[40..48].try_into().unwrap()),
+            44.. => u32::from_le_bytes(data[40..44].try_into().unwrap()) as u64,
+            _ => return None,
+        };
+        for (i, select) in (0x08..=0x0c).enumerate() {
+            rtc.registers.write_byte(select, data[i * 4]);
+            rtc.latched.write_byte(select, data[20 + i * 4]);
+        }
+        rtc.clocks = 0;
+        Some(timestamp)
+    }
+
+    fn advance_rtc(&mut self, seconds: u64) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            if !rtc.registers.halt {
+                rtc.registers.advance(seconds);
+            }
+        }
+    }
+}
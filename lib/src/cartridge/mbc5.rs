@@ -0,0 +1,74 @@
+use super::{MemoryBankController, RAM_BANK_SIZE, ROM_BANK_SIZE};
+
+#[derive(Debug)]
+pub struct MBC5 {
+    rom_banks: usize,
+    // 9 bit
+    rom_bank_number: u16,
+    ram_bank_number: u8,
+    ram_enable: bool,
+}
+
+impl MBC5 {
+    pub fn new(rom_banks: usize) -> MBC5 {
+        MBC5 {
+            rom_banks,
+            rom_bank_number: 1,
+            ram_bank_number: 0,
+            ram_enable: false,
+        }
+    }
+
+    fn rom_offset(&self) -> usize {
+        // bank 00 can be mapped to 0x4000-0x7fff
+        ROM_BANK_SIZE * (self.rom_bank_number as usize % self.rom_banks)
+    }
+
+    fn ram_index(&self, ram: &[u8], addr: u16) -> Option<usize> {
+        if !self.ram_enable || ram.is_empty() {
+            return None;
+        }
+        let offset = RAM_BANK_SIZE * self.ram_bank_number as usize;
+        Some(((addr & 0x1fff) as usize + offset) % ram.len())
+    }
+}
+
+impl MemoryBankController for MBC5 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            // ROM bank 00
+            0x0000..=0x3fff => rom[addr as usize],
+            // ROM bank 000-1ff
+            _ => rom[(addr & 0x3fff) as usize + self.rom_offset()],
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, v: u8) {
+        match addr {
+            // RAM enable
+            0x0000..=0x1fff => self.ram_enable = v & 0x0f == 0x0a,
+            // ROM bank number(lower 8 bit)
+            0x2000..=0x2fff => self.rom_bank_number = (self.rom_bank_number & 0x0100) | v as u16,
+            // ROM bank number(9th bit)
+            0x3000..=0x3fff => {
+                self.rom_bank_number = (self.rom_bank_number & 0x00ff) | ((v as u16 & 0x01) << 8)
+            }
+            // RAM bank number
+            0x4000..=0x5fff => self.ram_bank_number = v & 0x0f,
+            _ => (),
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        match self.ram_index(ram, addr) {
+            Some(i) => ram[i],
+            None => 0xff,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, v: u8) {
+        if let Some(i) = self.ram_index(ram, addr) {
+            ram[i] = v;
+        }
+    }
+}
@@ -0,0 +1,173 @@
+mod header;
+mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+mod rom_only;
+
+use std::fmt;
+
+pub use self::header::{CartridgeError, CartridgeHeader};
+use self::mbc1::MBC1;
+use self::mbc2::MBC2;
+use self::mbc3::MBC3;
+use self::mbc5::MBC5;
+use self::rom_only::RomOnly;
+
+pub const ROM_BANK_SIZE: usize = 16 * 1024;
+pub const RAM_BANK_SIZE: usize = 8 * 1024;
+
+// supported cartridge types with a battery: MBC1+RAM, MBC2, ROM+RAM, MBC3+TIMER(+RAM), MBC3+RAM,
+// MBC5+RAM, MBC5+RUMBLE+RAM
+const BATTERY_TYPES: [u8; 8] = [0x03, 0x06, 0x09, 0x0f, 0x10, 0x13, 0x1b, 0x1e];
+
+// Banking logic of a cartridge, selected by the cartridge type (0x0147)
+pub trait MemoryBankController: fmt::Debug + Send {
+    // ROM bank 00-NN (0x0000-0x7fff)
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8;
+    // MBC registers (0x0000-0x7fff)
+    fn write_rom(&mut self, addr: u16, v: u8);
+    // external RAM (0xa000-0xbfff)
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8;
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, v: u8);
+    // clocks the cartridge hardware (e.g. RTC)
+    fn run(&mut self, _ticks: u32) {}
+    // RTC state appended to the battery-backed RAM
+    fn save_rtc(&self) -> Option<Vec<u8>> {
+        None
+    }
+    // returns the UNIX time the footer was saved at
+    fn load_rtc(&mut self, _data: &[u8]) -> Option<u64> {
+        None
+    }
+    fn advance_rtc(&mut self, _seconds: u64) {}
+}
+
+#[derive(Debug)]
+pub struct Cartridge {
+    bios: Vec<u8>,
+    bios_enable: bool,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    header: CartridgeHeader,
+    mbc: Box<dyn MemoryBankController>,
+}
+
+impl Cartridge {
+    pub fn new(rom: Vec<u8>) -> Result<Cartridge, CartridgeError> {
+        let header = CartridgeHeader::new(&rom)?;
+        if rom.len() < header.rom_size {
+            return Err(CartridgeError::RomSizeMismatch {
+                expected: header.rom_size,
+                actual: rom.len(),
+            });
+        }
+
+        let rom_banks = header.rom_size / ROM_BANK_SIZE;
+        let mut ram_size = header.ram_size;
+        let mbc: Box<dyn MemoryBankController> = match header.cartridge_type {
+            // ROM ONLY, ROM+RAM, ROM+RAM+BATTERY
+            0x00 | 0x08 | 0x09 => Box::new(RomOnly::new()),
+            // MBC1, MBC1+RAM, MBC1+RAM+BATTERY
+            0x01..=0x03 => Box::new(MBC1::new(rom_banks)),
+            // MBC2, MBC2+BATTERY
+            0x05 | 0x06 => {
+                ram_size = mbc2::RAM_SIZE;
+                Box::new(MBC2::new(rom_banks))
+            }
+            // MBC3+TIMER+BATTERY, MBC3+TIMER+RAM+BATTERY
+            0x0f | 0x10 => Box::new(MBC3::new(rom_banks, true)),
+            // MBC3, MBC3+RAM, MBC3+RAM+BATTERY
+            0x11..=0x13 => Box::new(MBC3::new(rom_banks, false)),
+            // MBC5(+RAM)(+BATTERY), MBC5+RUMBLE(+RAM)(+BATTERY)
+            0x19..=0x1e => Box::new(MBC5::new(rom_banks)),
+            v => return Err(CartridgeError::UnsupportedType(v)),
+        };
+
+        Ok(Cartridge {
+            bios: Vec::<u8>::new(),
+            bios_enable: false,
+            rom,
+            ram: vec![0; ram_size],
+            header,
+            mbc,
+        })
+    }
+
+    pub fn header(&self) -> &CartridgeHeader {
+        &self.header
+    }
+
+    pub fn load_bios(&mut self, bios: Vec<u8>) {
+        self.bios = bios;
+        self.bios_enable = true;
+    }
+
+    pub fn is_bios_enabled(&self) -> bool {
+        self.bios_enable
+    }
+
+    // unmapped by writing to 0xff50; it cannot be mapped again until reset
+    pub fn disable_bios(&mut self) {
+        self.bios_enable = false;
+    }
+
+    pub fn read_byte(&self, addr: u16) -> u8 {
+        match addr {
+            // BIOS
+            0x0000..=0x00ff => {
+                if self.bios_enable {
+                    return self.bios[addr as usize];
+                }
+                self.mbc.read_rom(&self.rom, addr)
+            }
+            // CGB BIOS continues after the cartridge header
+            0x0200..=0x08ff if self.bios_enable && (addr as usize) < self.bios.len() => {
+                self.bios[addr as usize]
+            }
+            // ROM bank 00-NN
+            0x0100..=0x7fff => self.mbc.read_rom(&self.rom, addr),
+            // RAM bank 00-NN
+            0xa000..=0xbfff => self.mbc.read_ram(&self.ram, addr),
+            _ => 0xff,
+        }
+    }
+
+    pub fn write_byte(&mut self, addr: u16, v: u8) {
+        match addr {
+            0x0000..=0x7fff => self.mbc.write_rom(addr, v),
+            0xa000..=0xbfff => self.mbc.write_ram(&mut self.ram, addr, v),
+            _ => (),
+        }
+    }
+
+    pub fn run(&mut self, ticks: u32) {
+        self.mbc.run(ticks);
+    }
+
+    pub fn has_battery(&self) -> bool {
+        BATTERY_TYPES.contains(&self.header.cartridge_type)
+    }
+
+    // RAM contents followed by the RTC footer (MBC3+TIMER only)
+    pub fn save_ram(&self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+        if let Some(rtc) = self.mbc.save_rtc() {
+            data.extend(rtc);
+        }
+        data
+    }
+
+    // returns the UNIX time the RTC footer was saved at, so the caller can advance the clock
+    // by the time the game was not running
+    pub fn load_ram(&mut self, data: &[u8]) -> Option<u64> {
+        let len = self.ram.len().min(data.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+        self.mbc.load_rtc(data.get(self.ram.len()..)?)
+    }
+
+    // no-op without an RTC or while it is halted
+    pub fn advance_rtc(&mut self, seconds: u64) {
+        self.mbc.advance_rtc(seconds);
+    }
+}
@@ -0,0 +1,33 @@
+use super::MemoryBankController;
+
+#[derive(Debug)]
+pub struct RomOnly {}
+
+impl RomOnly {
+    pub fn new() -> RomOnly {
+        RomOnly {}
+    }
+}
+
+impl MemoryBankController for RomOnly {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        rom[(addr & 0x7fff) as usize]
+    }
+
+    fn write_rom(&mut self, _addr: u16, _v: u8) {}
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if ram.is_empty() {
+            return 0xff;
+        }
+        ram[(addr & 0x1fff) as usize % ram.len()]
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, v: u8) {
+        if ram.is_empty() {
+            return;
+        }
+        let len = ram.len();
+        ram[(addr & 0x1fff) as usize % len] = v;
+    }
+}
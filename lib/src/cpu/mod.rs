@@ -4,6 +4,8 @@ use self::register::Flags;
 use self::register::Register;
 use self::register::Registers16::{AF, BC, DE, HL};
 
+use crate::cartridge::CartridgeError;
+use crate::error::EmuError;
 use crate::mmu::{Interrupt, MMU};
 
 #[derive(Debug)]
@@ -14,37 +16,69 @@ pub struct CPU {
     ei: u8,
     di: u8,
     halted: bool,
+    locked: bool,
+    error: Option<EmuError>,
 }
 
 impl CPU {
-    pub fn new(rom: Vec<u8>) -> CPU {
-        CPU {
+    pub fn new(rom: Vec<u8>) -> Result<CPU, CartridgeError> {
+        Ok(CPU {
             register: Register::new(),
-            mmu: MMU::new(rom),
+            mmu: MMU::new(rom)?,
             ime: false,
             ei: 0,
             di: 0,
             halted: false,
-        }
+            locked: false,
+            error: None,
+        })
     }
 
     pub fn init(&mut self) {
-        self.register.write_word(AF, 0x01b0);
-        self.register.write_word(BC, 0x0013);
-        self.register.write_word(DE, 0x00d8);
-        self.register.write_word(HL, 0x014d);
+        if self.mmu.is_cgb() {
+            // A = 0x11 tells the game it runs on a CGB
+            self.register.write_word(AF, 0x1180);
+            self.register.write_word(BC, 0x0000);
+            self.register.write_word(DE, 0xff56);
+            self.register.write_word(HL, 0x000d);
+        } else {
+            self.register.write_word(AF, 0x01b0);
+            self.register.write_word(BC, 0x0013);
+            self.register.write_word(DE, 0x00d8);
+            self.register.write_word(HL, 0x014d);
+        }
         self.register.sp = 0xfffe;
         self.register.pc = 0x0100;
         self.mmu.init();
     }
 
-    pub fn run(&mut self) -> u32 {
-        let ticks = self.run_with_interrupt();
+    // returns the elapsed ticks of the 4.19 MHz base clock, which are half the CPU ticks in double speed mode
+    pub fn run(&mut self) -> Result<u32, EmuError> {
+        let mut ticks = self.run_with_interrupt();
         self.mmu.run(ticks);
-        ticks
+        // the CPU is halted while VRAM DMA copies blocks
+        let stall = self.mmu.take_stall();
+        if stall > 0 {
+            self.mmu.run(stall);
+            ticks += stall;
+        }
+        let ticks = if self.mmu.is_double_speed() {
+            ticks / 2
+        } else {
+            ticks
+        };
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(ticks),
+        }
     }
 
     pub fn run_with_interrupt(&mut self) -> u32 {
+        if self.locked {
+            // only a reset recovers from a lock-up
+            return 4;
+        }
+
         self.update_ime();
         match self.handle_interrupt() {
             0 => (),
@@ -108,8 +142,8 @@ impl CPU {
             Interrupt::VBLANK => self._call(0x0040),
             Interrupt::LCD_STAT => self._call(0x0048),
             Interrupt::TIMER => self._call(0x0050),
-            Interrupt::SERIAL => self._call(0x0080),
-            Interrupt::JOYPAD => self._call(0x0070),
+            Interrupt::SERIAL => self._call(0x0058),
+            Interrupt::JOYPAD => self._call(0x0060),
             _ => unreachable!("Invalid interrupt request: {:?}", request),
         };
         16
@@ -309,7 +343,7 @@ impl CPU {
 
             // miscellaneous
             0x00 => 4, // nop
-            0x10 => 4, // stop
+            0x10 => self.stop(),
             0x27 => self.dda(),
             0x2f => self.cpl(),
             0x37 => self.scf(),
@@ -319,7 +353,11 @@ impl CPU {
             0xfb => self.ei(),
 
             0xcb => self.prefix(),
-            _ => unimplemented!("unknown opcode: 0x{:02x}\ncpu: {:?}", opcode, self),
+
+            // illegal
+            0xd3 | 0xdb | 0xdd | 0xe3 | 0xe4 | 0xeb | 0xec | 0xed | 0xf4 | 0xfc | 0xfd => {
+                self.lock_up(opcode)
+            }
         }
     }
 
@@ -339,7 +377,6 @@ impl CPU {
             0x40..=0x7f => self.bit(opcode),
             0x80..=0xbf => self.res(opcode),
             0xc0..=0xff => self.set(opcode),
-            _ => unimplemented!("unknown cb opcode: 0x{:02x}\ncput: {:?}", opcode, self),
         }
     }
 
@@ -1143,6 +1180,25 @@ impl CPU {
         4
     }
 
+    fn lock_up(&mut self, opcode: u8) -> u32 {
+        self.locked = true;
+        self.error = Some(EmuError::IllegalOpcode {
+            opcode,
+            addr: self.register.pc.wrapping_sub(1),
+        });
+        4
+    }
+
+    fn stop(&mut self) -> u32 {
+        // stop is followed by a padding byte
+        self.fetch_byte();
+        if self.mmu.switch_speed() {
+            // the CPU pauses for 2050 M-cycles while the clock settles
+            return 2050 * 4;
+        }
+        4
+    }
+
     fn halt(&mut self) -> u32 {
         self.halted = true;
         4
@@ -0,0 +1,20 @@
+use std::error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmuError {
+    // the CPU locks up until reset, like the hardware does
+    IllegalOpcode { opcode: u8, addr: u16 },
+}
+
+impl fmt::Display for EmuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmuError::IllegalOpcode { opcode, addr } => {
+                write!(f, "illegal opcode: 0x{:02x} at 0x{:04x}", opcode, addr)
+            }
+        }
+    }
+}
+
+impl error::Error for EmuError {}
@@ -0,0 +1,75 @@
+pub const BLOCK_SIZE: u16 = 16;
+
+// CGB VRAM DMA (0xff51-0xff55); the MMU copies the blocks
+#[derive(Debug)]
+pub struct Hdma {
+    src: u16,
+    dst: u16,
+    // remaining blocks - 1, as read from HDMA5
+    length: u8,
+    active: bool,
+    // false = general purpose DMA, true = HBlank DMA
+    hblank: bool,
+}
+
+impl Hdma {
+    pub fn new() -> Hdma {
+        Hdma {
+            src: 0,
+            dst: 0,
+            length: 0x7f,
+            active: false,
+            hblank: false,
+        }
+    }
+
+    pub fn read_byte(&self, addr: u16) -> u8 {
+        match addr {
+            // bit 7 is cleared while HBlank DMA is in progress
+            0xff55 => (!self.active as u8) << 7 | self.length,
+            // write only
+            _ => 0xff,
+        }
+    }
+
+    pub fn write_byte(&mut self, addr: u16, v: u8) {
+        match addr {
+            0xff51 => self.src = (self.src & 0x00ff) | (v as u16) << 8,
+            0xff52 => self.src = (self.src & 0xff00) | (v & 0xf0) as u16,
+            // always in VRAM
+            0xff53 => self.dst = (self.dst & 0x00ff) | ((v & 0x1f) as u16) << 8,
+            0xff54 => self.dst = (self.dst & 0xff00) | (v & 0xf0) as u16,
+            // writing bit 7 = 0 during HBlank DMA cancels it
+            0xff55 if self.active && self.hblank && v & 0x80 == 0 => self.active = false,
+            0xff55 => {
+                self.length = v & 0x7f;
+                self.hblank = v & 0x80 != 0;
+                self.active = true;
+            }
+            _ => (),
+        }
+    }
+
+    pub fn is_general_active(&self) -> bool {
+        self.active && !self.hblank
+    }
+
+    pub fn is_hblank_active(&self) -> bool {
+        self.active && self.hblank
+    }
+
+    // source and VRAM destination of the next 16 bytes
+    pub fn next_block(&mut self) -> Option<(u16, u16)> {
+        if !self.active {
+            return None;
+        }
+        let block = (self.src, 0x8000 | self.dst);
+        self.src = self.src.wrapping_add(BLOCK_SIZE);
+        self.dst = (self.dst + BLOCK_SIZE) & 0x1ff0;
+        self.length = self.length.wrapping_sub(1) & 0x7f;
+        if self.length == 0x7f {
+            self.active = false;
+        }
+        Some(block)
+    }
+}
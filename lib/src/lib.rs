@@ -1,11 +1,22 @@
 mod apu;
 mod cartridge;
 pub mod cpu;
+mod error;
+mod hdma;
 pub mod joypad;
+mod link;
 mod mmu;
+mod oam_dma;
 mod ppu;
+mod printer;
 mod serial;
 mod timer;
 
+pub use cartridge::{CartridgeError, CartridgeHeader};
 pub use cpu::CPU;
+pub use error::EmuError;
 pub use joypad::KeyInput;
+pub use link::LinkCable;
+pub use ppu::{Palette, Renderer, Shades};
+pub use printer::{Printer, Printout, PRINTOUT_WIDTH};
+pub use serial::{SerialCapture, SerialDevice};
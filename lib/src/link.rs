@@ -0,0 +1,104 @@
+use std::sync::{Arc, Mutex};
+
+use crate::cpu::CPU;
+use crate::error::EmuError;
+use crate::serial::SerialDevice;
+
+// state of the cable shared by both ports
+#[derive(Debug, Default)]
+struct Wire {
+    // SB of a side waiting for the external clock
+    waiting: [Option<u8>; 2],
+    // byte clocked into a waiting side, picked up by its next poll
+    incoming: [Option<u8>; 2],
+}
+
+#[derive(Debug)]
+struct Port {
+    wire: Arc<Mutex<Wire>>,
+    side: usize,
+}
+
+impl SerialDevice for Port {
+    fn exchange(&mut self, data: u8) -> u8 {
+        let mut wire = self.wire.lock().unwrap();
+        let peer = self.side ^ 1;
+        wire.waiting[self.side] = None;
+        match wire.waiting[peer].take() {
+            Some(v) => {
+                wire.incoming[peer] = Some(data);
+                v
+            }
+            // the peer isn't waiting for a transfer; nothing is on the line
+            None => 0xff,
+        }
+    }
+
+    fn poll_external(&mut self, data: u8) -> Option<u8> {
+        let mut wire = self.wire.lock().unwrap();
+        let v = wire.incoming[self.side].take();
+        if v.is_none() {
+            wire.waiting[self.side] = Some(data);
+        }
+        v
+    }
+
+    fn control_written(&mut self) {
+        let mut wire = self.wire.lock().unwrap();
+        wire.waiting[self.side] = None;
+        wire.incoming[self.side] = None;
+    }
+}
+
+// two consoles connected by a link cable, run in lockstep in one thread
+#[derive(Debug)]
+pub struct LinkCable {
+    pub left: CPU,
+    pub right: CPU,
+    // base clock ticks the left console is ahead of the right one
+    skew: i64,
+}
+
+impl LinkCable {
+    pub fn new(mut left: CPU, mut right: CPU) -> LinkCable {
+        let wire = Arc::new(Mutex::new(Wire::default()));
+        left.mmu.serial.set_device(Box::new(Port {
+            wire: wire.clone(),
+            side: 0,
+        }));
+        right
+            .mmu
+            .serial
+            .set_device(Box::new(Port { wire, side: 1 }));
+        LinkCable {
+            left,
+            right,
+            skew: 0,
+        }
+    }
+
+    // runs one instruction on the console that is behind; returns the ticks of each console
+    pub fn run(&mut self) -> Result<(u32, u32), EmuError> {
+        if self.skew <= 0 {
+            let ticks = self.left.run()?;
+            self.skew += ticks as i64;
+            Ok((ticks, 0))
+        } else {
+            let ticks = self.right.run()?;
+            self.skew -= ticks as i64;
+            Ok((0, ticks))
+        }
+    }
+
+    // runs both consoles for at least `ticks` base clock ticks
+    pub fn run_ticks(&mut self, ticks: u32) -> Result<(), EmuError> {
+        let mut left = 0;
+        let mut right = 0;
+        while left < ticks || right < ticks {
+            let (l, r) = self.run()?;
+            left += l;
+            right += r;
+        }
+        Ok(())
+    }
+}
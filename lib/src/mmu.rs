@@ -1,25 +1,38 @@
 use bitflags::bitflags;
 
 use crate::apu::APU;
-use crate::cartridge::Cartridge;
+use crate::cartridge::{Cartridge, CartridgeError};
+use crate::hdma::{Hdma, BLOCK_SIZE};
 use crate::joypad::JoyPad;
-use crate::ppu::{OAM_SIZE, PPU};
+use crate::oam_dma::OamDma;
+use crate::ppu::PPU;
 use crate::serial::Serial;
 use crate::timer::Timer;
 
-const WORKING_RAM_SIZE: usize = 8 * 1024;
+const WORKING_RAM_BANK_SIZE: usize = 4 * 1024;
+const WORKING_RAM_BANKS: usize = 8;
 const HIGH_RAM_SIZE: usize = 128;
 
 #[derive(Debug)]
 pub struct MMU {
     pub cartridge: Cartridge,
-    wram: [u8; WORKING_RAM_SIZE],
+    cgb: bool,
+    wram: [u8; WORKING_RAM_BANK_SIZE * WORKING_RAM_BANKS],
+    // SVBK (CGB only): WRAM bank mapped to 0xd000-0xdfff
+    svbk: u8,
+    // KEY1 (CGB only): speed switch armed by bit 0, performed by STOP
+    speed_switch: bool,
+    double_speed: bool,
+    hdma: Hdma,
+    oam_dma: OamDma,
+    // CPU ticks the CPU is stalled by VRAM DMA
+    stall: u32,
     hram: [u8; HIGH_RAM_SIZE],
     pub ppu: PPU,
-    apu: APU,
+    pub apu: APU,
     pub interrupt_enable: Interrupt,
     pub interrupt_flag: Interrupt,
-    serial: Serial,
+    pub serial: Serial,
     timer: Timer,
     pub joypad: JoyPad,
 }
@@ -35,25 +48,60 @@ bitflags!(
 );
 
 impl MMU {
-    pub fn new(rom: Vec<u8>) -> MMU {
-        MMU {
-            cartridge: Cartridge::new(rom),
-            wram: [0; WORKING_RAM_SIZE],
+    pub fn new(rom: Vec<u8>) -> Result<MMU, CartridgeError> {
+        let cartridge = Cartridge::new(rom)?;
+        let cgb = cartridge.header().is_cgb();
+        Ok(MMU {
+            cartridge,
+            cgb,
+            wram: [0; WORKING_RAM_BANK_SIZE * WORKING_RAM_BANKS],
+            svbk: 0,
+            speed_switch: false,
+            double_speed: false,
+            hdma: Hdma::new(),
+            oam_dma: OamDma::new(),
+            stall: 0,
             hram: [0; HIGH_RAM_SIZE],
-            ppu: PPU::new(),
+            ppu: PPU::new(cgb),
             apu: APU::new(),
             interrupt_enable: Interrupt::empty(),
             interrupt_flag: Interrupt::empty(),
-            serial: Serial::new(),
+            serial: Serial::new(cgb),
             timer: Timer::new(),
             joypad: JoyPad::new(),
+        })
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        self.cgb
+    }
+
+    pub fn is_double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    // called by STOP; returns true if the speed was switched
+    pub fn switch_speed(&mut self) -> bool {
+        if !self.cgb || !self.speed_switch {
+            return false;
         }
+        self.speed_switch = false;
+        self.double_speed = !self.double_speed;
+        // STOP resets DIV
+        self.timer.write_byte(0xff04, 0);
+        true
+    }
+
+    pub fn take_stall(&mut self) -> u32 {
+        std::mem::take(&mut self.stall)
     }
 
     pub fn init(&mut self) {
         self.write_byte(0xff05, 0x00);
         self.write_byte(0xff06, 0x00);
         self.write_byte(0xff07, 0x00);
+        // sound registers can be written only after power on
+        self.write_byte(0xff26, 0xf1);
         self.write_byte(0xff10, 0x80);
         self.write_byte(0xff11, 0xbf);
         self.write_byte(0xff12, 0xf3);
@@ -71,7 +119,6 @@ impl MMU {
         self.write_byte(0xff23, 0xbf);
         self.write_byte(0xff24, 0x77);
         self.write_byte(0xff25, 0xf3);
-        self.write_byte(0xff26, 0xf1);
         self.write_byte(0xff40, 0x91);
         self.write_byte(0xff42, 0x00);
         self.write_byte(0xff43, 0x00);
@@ -84,9 +131,22 @@ impl MMU {
         self.write_byte(0xffff, 0x00);
     }
 
+    // ticks of the CPU clock; the cartridge, PPU and APU keep the base clock in double speed mode
     pub fn run(&mut self, ticks: u32) {
-        self.ppu.run(ticks);
+        let base_ticks = if self.double_speed { ticks / 2 } else { ticks };
+        self.oam_dma_transfer(ticks);
+        self.cartridge.run(base_ticks);
+        self.ppu.run(base_ticks);
+        self.apu.run(base_ticks);
         self.timer.run(ticks);
+        self.serial.run(ticks);
+
+        if self.ppu.hblank {
+            self.ppu.hblank = false;
+            if self.hdma.is_hblank_active() {
+                self.hdma_transfer();
+            }
+        }
 
         if self.ppu.interrupt_vblank {
             self.interrupt_flag.set(Interrupt::VBLANK, true);
@@ -102,78 +162,133 @@ impl MMU {
             self.interrupt_flag.set(Interrupt::TIMER, true);
             self.timer.interrupt = false;
         }
+
+        if self.serial.interrupt {
+            self.interrupt_flag.set(Interrupt::SERIAL, true);
+            self.serial.interrupt = false;
+        }
     }
 
-    fn dma_transfer(&mut self, v: u8) {
-        let src = (v as u16) << 8;
-        for i in 0..OAM_SIZE as u16 {
-            let b = self.read_byte(src | i);
-            self.write_byte(0xfe00 | i, b);
+    // copies one block (HBlank DMA) or all of them (general purpose DMA)
+    fn hdma_transfer(&mut self) {
+        while let Some((src, dst)) = self.hdma.next_block() {
+            for i in 0..BLOCK_SIZE {
+                let b = self.read_bus(src.wrapping_add(i));
+                self.ppu.write_byte(dst + i, b);
+            }
+            // 8 M-cycles per block at normal speed, 16 at double speed
+            self.stall += if self.double_speed { 64 } else { 32 };
+            if !self.hdma.is_general_active() {
+                break;
+            }
+        }
+    }
+
+    // OAM is written directly, so the transfer is not blocked by the PPU mode
+    fn oam_dma_transfer(&mut self, ticks: u32) {
+        for _ in 0..self.oam_dma.run(ticks) {
+            let (src, i) = self.oam_dma.next_byte();
+            let b = self.read_bus(src);
+            self.oam_dma.set_bus(b);
+            self.ppu.oam[i] = b;
+        }
+    }
+
+    // 0xc000-0xcfff is always bank 0; bank 0 cannot be selected for 0xd000-0xdfff
+    fn wram_index(&self, addr: u16) -> usize {
+        let offset = (addr & 0x0fff) as usize;
+        if addr & 0x1000 == 0 {
+            return offset;
         }
+        let bank = match self.svbk & 0x07 {
+            0 => 1,
+            n => n as usize,
+        };
+        bank * WORKING_RAM_BANK_SIZE + offset
     }
 
+    // the CPU can reach only the high page (I/O and HRAM) while OAM DMA owns the bus
     pub fn read_byte(&self, addr: u16) -> u8 {
+        if self.oam_dma.is_active() && addr < 0xff00 {
+            return self.oam_dma.bus();
+        }
+        self.read_bus(addr)
+    }
+
+    fn read_bus(&self, addr: u16) -> u8 {
         match addr {
             0x0000..=0x7fff => self.cartridge.read_byte(addr),
             0x8000..=0x9fff => self.ppu.read_byte(addr),
             0xa000..=0xbfff => self.cartridge.read_byte(addr),
-            0xc000..=0xdfff => self.wram[(addr & (WORKING_RAM_SIZE as u16 - 1)) as usize],
-            0xe000..=0xfdff => {
-                self.wram[((addr - 0x2000) & (WORKING_RAM_SIZE as u16 - 1)) as usize]
-            }
+            0xc000..=0xfdff => self.wram[self.wram_index(addr)],
             0xfe00..=0xfe9f => self.ppu.read_byte(addr),
             0xfea0..=0xfeff => 0xff, // unused
             0xff00 => self.joypad.read_byte(addr),
             0xff01..=0xff02 => self.serial.read_byte(addr),
             0xff04..=0xff07 => self.timer.read_byte(addr),
             0xff0f => self.interrupt_flag.bits,
-            0xff03 | 0xff08..=0xff0e | 0xff4c | 0xff4e..=0xff4f | 0xff51..=0xff7e => {
-                unimplemented!("read: I/O register: {:x}", addr)
-            }
-            0xff4d => 0xff, // CGB register
+            // unmapped I/O registers read as open bus
+            0xff51..=0xff55 if self.cgb => self.hdma.read_byte(addr),
+            0xff03 | 0xff08..=0xff0e | 0xff4c | 0xff4e | 0xff51..=0xff67 => 0xff,
+            0xff6d..=0xff6f | 0xff71..=0xff7e => 0xff,
+            0xff4d if self.cgb => (self.double_speed as u8) << 7 | 0x7e | self.speed_switch as u8,
+            0xff4d => 0xff,
             0xff10..=0xff3f => self.apu.read_byte(addr),
-            0xff40..=0xff45 | 0xff47..=0xff4b => self.ppu.read_byte(addr),
-            0xff46 => 0xff,
-            0xff50 => panic!("BIOS DISABLE"),
+            0xff40..=0xff45 | 0xff47..=0xff4b | 0xff4f => self.ppu.read_byte(addr),
+            0xff68..=0xff6c => self.ppu.read_byte(addr),
+            0xff70 if self.cgb => 0xf8 | self.svbk,
+            0xff70 => 0xff,
+            0xff46 => self.oam_dma.read_byte(),
+            // boot ROM disable (bit 0 is set once unmapped)
+            0xff50 => 0xfe | !self.cartridge.is_bios_enabled() as u8,
             0xff7f => 0xff, // unused
             0xff80..=0xfffe => self.hram[(addr & (HIGH_RAM_SIZE as u16 - 1)) as usize],
             0xffff..=0xffff => self.interrupt_enable.bits,
-            _ => 0xff,
         }
     }
 
     pub fn write_byte(&mut self, addr: u16, v: u8) {
+        if self.oam_dma.is_active() && addr < 0xff00 {
+            return;
+        }
         match addr {
             0x0000..=0x7fff => self.cartridge.write_byte(addr, v),
             0x8000..=0x9fff => self.ppu.write_byte(addr, v),
             0xa000..=0xbfff => self.cartridge.write_byte(addr, v),
-            0xc000..=0xdfff => self.wram[(addr & (WORKING_RAM_SIZE as u16 - 1)) as usize] = v,
-            0xe000..=0xfdff => {
-                self.wram[((addr - 0x2000) & (WORKING_RAM_SIZE as u16 - 1)) as usize] = v;
-            }
+            0xc000..=0xfdff => self.wram[self.wram_index(addr)] = v,
             0xfe00..=0xfe9f => self.ppu.write_byte(addr, v),
             0xfea0..=0xfeff => (), // unused
             0xff00 => self.joypad.write_byte(addr, v),
             0xff01..=0xff02 => self.serial.write_byte(addr, v),
             0xff04..=0xff07 => self.timer.write_byte(addr, v),
             0xff0f => self.interrupt_flag = Interrupt::from_bits_truncate(v),
-            0xff03 | 0xff08..=0xff0e | 0xff4c | 0xff4e..=0xff4f | 0xff51..=0xff7e => {
-                unimplemented!("write: I/O register: {:04x} {:02x}", addr, v)
+            0xff51..=0xff55 if self.cgb => {
+                self.hdma.write_byte(addr, v);
+                if self.hdma.is_general_active() {
+                    self.hdma_transfer();
+                }
             }
-            0xff4d => (), // CGB register
+            0xff03 | 0xff08..=0xff0e | 0xff4c | 0xff4e | 0xff51..=0xff67 => (),
+            0xff6d..=0xff6f | 0xff71..=0xff7e => (),
+            0xff4d if self.cgb => self.speed_switch = v & 0x01 != 0,
+            0xff4d => (),
             0xff10..=0xff3f => self.apu.write_byte(addr, v),
-            0xff40..=0xff45 | 0xff47..=0xff4b => self.ppu.write_byte(addr, v),
-            0xff46 => self.dma_transfer(v),
-            0xff50 => panic!("BIOS DISABLE"),
+            0xff40..=0xff45 | 0xff47..=0xff4b | 0xff4f => self.ppu.write_byte(addr, v),
+            0xff68..=0xff6c => self.ppu.write_byte(addr, v),
+            0xff70 if self.cgb => self.svbk = v & 0x07,
+            0xff70 => (),
+            0xff46 => self.oam_dma.write_byte(v),
+            // boot ROM disable
+            0xff50 if v & 0x01 != 0 => self.cartridge.disable_bios(),
+            0xff50 => (),
             0xff7f => (), // unused
             0xff80..=0xfffe => self.hram[(addr & (HIGH_RAM_SIZE as u16 - 1)) as usize] = v,
             0xffff..=0xffff => self.interrupt_enable = Interrupt::from_bits_truncate(v),
-            _ => unreachable!("write: not support the address: {:04x} {:02x}", addr, v),
         }
     }
 
     pub fn read_word(&self, addr: u16) -> u16 {
-        ((self.read_byte(addr + 1) as u16) << 8) | self.read_byte(addr) as u16
+        ((self.read_byte(addr.wrapping_add(1)) as u16) << 8) | self.read_byte(addr) as u16
     }
 
     pub fn write_word(&mut self, addr: u16, v: u16) {
@@ -0,0 +1,82 @@
+use crate::ppu::OAM_SIZE;
+
+// OAM DMA (0xff46): one byte per M-cycle, 160 M-cycles in total; the MMU copies the bytes
+#[derive(Debug)]
+pub struct OamDma {
+    register: u8,
+    index: usize,
+    clocks: u32,
+    // the write itself takes the rest of the instruction before the transfer starts
+    starting: bool,
+    active: bool,
+    // last byte transferred, seen by the CPU on reads outside the high page
+    bus: u8,
+}
+
+impl OamDma {
+    pub fn new() -> OamDma {
+        OamDma {
+            register: 0xff,
+            index: 0,
+            clocks: 0,
+            starting: false,
+            active: false,
+            bus: 0xff,
+        }
+    }
+
+    pub fn read_byte(&self) -> u8 {
+        self.register
+    }
+
+    // starts (or restarts) a transfer from 0xXX00
+    pub fn write_byte(&mut self, v: u8) {
+        self.register = v;
+        self.index = 0;
+        self.clocks = 0;
+        self.starting = true;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn bus(&self) -> u8 {
+        self.bus
+    }
+
+    // returns how many bytes are due after `ticks` CPU clocks
+    pub fn run(&mut self, ticks: u32) -> usize {
+        if self.starting {
+            self.starting = false;
+            self.active = true;
+            return 0;
+        }
+        if !self.active {
+            return 0;
+        }
+        self.clocks += ticks;
+        let n = ((self.clocks / 4) as usize).min(OAM_SIZE - self.index);
+        self.clocks %= 4;
+        n
+    }
+
+    // source address and OAM index of the next byte
+    pub fn next_byte(&mut self) -> (u16, usize) {
+        let i = self.index;
+        self.index += 1;
+        if self.index == OAM_SIZE {
+            self.active = false;
+        }
+        // pages 0xe0-0xff read WRAM (0xc000-0xdfff), not echo RAM, OAM or I/O
+        let page = match self.register {
+            0xe0..=0xff => self.register - 0x20,
+            v => v,
+        };
+        ((page as u16) << 8 | i as u16, i)
+    }
+
+    pub fn set_bus(&mut self, v: u8) {
+        self.bus = v;
+    }
+}
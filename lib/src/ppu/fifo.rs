@@ -0,0 +1,303 @@
+use std::collections::VecDeque;
+
+use super::{
+    BgPixel, Control, Sprite, SpriteFlags, TileAttributes, PPU, SCREEN_WIDTH, VRAM_BANK_SIZE,
+};
+
+// dots a sprite fetch stalls the FIFO once the BG fetcher is idle
+const SPRITE_FETCH_CLOCKS: u8 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FetchStep {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FifoPixel {
+    color_num: u8,
+    attributes: TileAttributes,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ObjPixel {
+    color_num: u8,
+    flags: SpriteFlags,
+    addr: usize,
+}
+
+// state of mode 3 for the FIFO renderer
+#[derive(Debug)]
+pub struct Fifo {
+    active: bool,
+    bg: VecDeque<FifoPixel>,
+    obj: VecDeque<ObjPixel>,
+    step: FetchStep,
+    // fetcher steps other than push take 2 dots
+    half: bool,
+    // tile column of the next fetch
+    fetch_x: u8,
+    tile_addr: usize,
+    attributes: TileAttributes,
+    line: usize,
+    data_lo: u8,
+    data_hi: u8,
+    // the first fetch of a line is thrown away
+    first_fetch: bool,
+    window: bool,
+    // pixels sent to the LCD
+    lx: u8,
+    // SCX fine scroll pixels dropped at the start of the line
+    discard: u8,
+    // sprites not fetched yet, sorted by x
+    sprites: Vec<Sprite>,
+    sprite_fetch: Option<(Sprite, u8)>,
+}
+
+impl Fifo {
+    pub fn new() -> Fifo {
+        Fifo {
+            active: false,
+            bg: VecDeque::with_capacity(16),
+            obj: VecDeque::with_capacity(8),
+            step: FetchStep::Tile,
+            half: false,
+            fetch_x: 0,
+            tile_addr: 0,
+            attributes: TileAttributes::empty(),
+            line: 0,
+            data_lo: 0,
+            data_hi: 0,
+            first_fetch: true,
+            window: false,
+            lx: 0,
+            discard: 0,
+            sprites: Vec::with_capacity(10),
+            sprite_fetch: None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    fn restart_fetcher(&mut self) {
+        self.step = FetchStep::Tile;
+        self.half = false;
+        self.fetch_x = 0;
+    }
+}
+
+impl PPU {
+    pub(super) fn start_fifo_line(&mut self) {
+        let mut sprites = self.scan_oam();
+        // x = 0 hides the sprite
+        sprites.retain(|s| s.x != 0);
+        sprites.sort_by_key(|s| s.x);
+
+        let fifo = &mut self.fifo;
+        fifo.active = true;
+        fifo.bg.clear();
+        fifo.obj.clear();
+        fifo.restart_fetcher();
+        fifo.first_fetch = true;
+        fifo.window = false;
+        fifo.lx = 0;
+        fifo.discard = self.scx & 0x07;
+        fifo.sprites = sprites;
+        fifo.sprite_fetch = None;
+    }
+
+    fn window_visible(&self) -> bool {
+        self.control.contains(Control::WINDOW_ENABLE)
+            && (self.cgb || self.control.contains(Control::BG_ENABLE))
+            && self.wy_triggered
+    }
+
+    // one dot of mode 3
+    pub(super) fn step_fifo(&mut self) {
+        if let Some((sprite, dots)) = self.fifo.sprite_fetch.take() {
+            // the BG fetch in progress is finished first
+            if self.fifo.step != FetchStep::Push {
+                self.advance_fetcher();
+                self.fifo.sprite_fetch = Some((sprite, dots));
+            } else if dots + 1 < SPRITE_FETCH_CLOCKS {
+                self.fifo.sprite_fetch = Some((sprite, dots + 1));
+            } else {
+                self.load_sprite(&sprite);
+            }
+            return;
+        }
+
+        if !self.fifo.window && self.window_visible() && self.fifo.lx as i16 >= self.window_x() {
+            self.fifo.window = true;
+            self.fifo.bg.clear();
+            // WX < 7: the window pixels left of the screen are dropped
+            self.fifo.discard = (-self.window_x()).max(0) as u8;
+            self.fifo.restart_fetcher();
+        }
+
+        if self.control.contains(Control::OBJ_ENABLE)
+            && self.fifo.discard == 0
+            && !self.fifo.bg.is_empty()
+            && self
+                .fifo
+                .sprites
+                .first()
+                .is_some_and(|s| s.x as u16 <= self.fifo.lx as u16 + 8)
+        {
+            let sprite = self.fifo.sprites.remove(0);
+            self.fifo.sprite_fetch = Some((sprite, 0));
+            return;
+        }
+
+        if let Some(px) = self.fifo.bg.pop_front() {
+            if self.fifo.discard > 0 {
+                self.fifo.discard -= 1;
+            } else {
+                let obj = self.fifo.obj.pop_front();
+                let color = self.mix_pixel(px, obj);
+                let lx = self.fifo.lx as usize;
+                self.frame_buffer[SCREEN_WIDTH * self.ly as usize + lx] = color;
+                self.fifo.lx += 1;
+                if self.fifo.lx as usize == SCREEN_WIDTH {
+                    self.fifo.active = false;
+                    if self.fifo.window {
+                        self.window_line = self.window_line.wrapping_add(1);
+                    }
+                    self.window_wrap = self.fifo.window && self.wx == 166;
+                    return;
+                }
+            }
+        }
+
+        self.advance_fetcher();
+    }
+
+    fn advance_fetcher(&mut self) {
+        if self.fifo.step != FetchStep::Push {
+            self.fifo.half = !self.fifo.half;
+            if self.fifo.half {
+                return;
+            }
+        }
+
+        match self.fifo.step {
+            FetchStep::Tile => {
+                self.fetch_tile();
+                self.fifo.step = FetchStep::DataLow;
+            }
+            FetchStep::DataLow => {
+                self.fifo.data_lo = self.fetch_tile_data(0);
+                self.fifo.step = FetchStep::DataHigh;
+            }
+            FetchStep::DataHigh => {
+                self.fifo.data_hi = self.fetch_tile_data(1);
+                self.fifo.step = FetchStep::Push;
+                self.push_fetched();
+            }
+            FetchStep::Push => self.push_fetched(),
+        }
+    }
+
+    fn fetch_tile(&mut self) {
+        let (map, x, y) = if self.fifo.window {
+            let x = (self.fifo.fetch_x & 0x1f) * 8;
+            (self.window_tile_map(), x, self.window_line)
+        } else {
+            let x = ((self.scx >> 3).wrapping_add(self.fifo.fetch_x) & 0x1f) * 8;
+            (self.bg_tile_map(), x, self.ly.wrapping_add(self.scy))
+        };
+        let (tile_addr, attributes) = self.map_tile(map, x, y);
+        self.fifo.tile_addr = tile_addr;
+        self.fifo.attributes = attributes;
+        self.fifo.line = if attributes.contains(TileAttributes::FLIP_Y) {
+            7 - (y & 0x07)
+        } else {
+            y & 0x07
+        } as usize;
+    }
+
+    fn fetch_tile_data(&self, offset: usize) -> u8 {
+        let bank = self.fifo.attributes.contains(TileAttributes::BANK) as usize;
+        let addr = (self.fifo.tile_addr | (self.fifo.line * 2)) & 0x1fff;
+        self.vram[bank * VRAM_BANK_SIZE + addr + offset]
+    }
+
+    // the fetcher waits until the BG FIFO is empty
+    fn push_fetched(&mut self) {
+        let fifo = &mut self.fifo;
+        if !fifo.bg.is_empty() {
+            return;
+        }
+        fifo.step = FetchStep::Tile;
+        if fifo.first_fetch {
+            fifo.first_fetch = false;
+            return;
+        }
+
+        for i in 0..8 {
+            let x = if fifo.attributes.contains(TileAttributes::FLIP_X) {
+                7 - i
+            } else {
+                i
+            };
+            let bit = 7 - x;
+            fifo.bg.push_back(FifoPixel {
+                color_num: (((fifo.data_hi >> bit) & 1) << 1) | ((fifo.data_lo >> bit) & 1),
+                attributes: fifo.attributes,
+            });
+        }
+        fifo.fetch_x = fifo.fetch_x.wrapping_add(1);
+    }
+
+    // merges a sprite into the OBJ FIFO; pixels already there keep their place unless transparent
+    fn load_sprite(&mut self, sprite: &Sprite) {
+        // pixels left of the current position are gone
+        let skip = (self.fifo.lx + 8).saturating_sub(sprite.x);
+        for i in skip..8 {
+            let px = ObjPixel {
+                color_num: self.sprite_pixel(sprite, i),
+                flags: sprite.flags,
+                addr: sprite.addr,
+            };
+            let j = (i - skip) as usize;
+            if j >= self.fifo.obj.len() {
+                self.fifo.obj.push_back(px);
+                continue;
+            }
+            let current = self.fifo.obj[j];
+            // CGB: the lower OAM index wins instead of the sprite fetched first
+            if current.color_num == 0
+                || (px.color_num != 0 && !self.obj_priority_x && px.addr < current.addr)
+            {
+                self.fifo.obj[j] = px;
+            }
+        }
+    }
+
+    fn mix_pixel(&self, px: FifoPixel, obj: Option<ObjPixel>) -> u32 {
+        let bg_enable = self.cgb || self.control.contains(Control::BG_ENABLE);
+        let bg = BgPixel {
+            color_num: if bg_enable { px.color_num } else { 0 },
+            priority: px.attributes.contains(TileAttributes::PRIORITY),
+        };
+
+        if let Some(obj) = obj {
+            if obj.color_num != 0
+                && self.control.contains(Control::OBJ_ENABLE)
+                && !self.obj_hidden(bg, obj.flags)
+            {
+                return self.obj_color(obj.flags, obj.color_num);
+            }
+        }
+
+        if bg_enable {
+            self.bg_color(px.attributes, px.color_num)
+        } else {
+            self.palette.bg[0]
+        }
+    }
+}
@@ -0,0 +1,722 @@
+mod fifo;
+mod palette;
+
+use bitflags::bitflags;
+use std::cmp::Ordering;
+
+use self::fifo::Fifo;
+pub use self::palette::{Palette, Shades};
+
+const VRAM_BANK_SIZE: usize = 8 * 1024;
+const VRAM_BANKS: usize = 2;
+pub const OAM_SIZE: usize = 160;
+const PALETTE_RAM_SIZE: usize = 64;
+
+const SCREEN_WIDTH: usize = 160;
+const SCREEN_HEIGHT: usize = 144;
+const SCREEN_PIXELS: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
+
+// dots per line without mode 2 (80)
+const LINE_CLOCKS_AFTER_OAM: u32 = 376;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Renderer {
+    // draws the whole line at the start of mode 3; fast, mode 3 is always 172 dots
+    Scanline,
+    // pixel fetcher and FIFOs stepped per dot; picks up mid-line register writes
+    Fifo,
+}
+
+#[derive(Debug)]
+pub struct PPU {
+    cgb: bool,
+    vram: [u8; VRAM_BANK_SIZE * VRAM_BANKS],
+    vram_bank: usize,
+    pub oam: [u8; OAM_SIZE],
+    mode: Mode,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    palette: Palette,
+    bg_palette: ColorPalette,
+    obj_palette: ColorPalette,
+    // CGB: false = OAM index priority, true = X coordinate priority (DMG style)
+    obj_priority_x: bool,
+    clocks: u32,
+    renderer: Renderer,
+    fifo: Fifo,
+    // length of mode 3 on the current line
+    mode3_clocks: u32,
+    ly: u8,
+    lyc: u8,
+    stat: Stat,
+    // OR of the enabled STAT interrupt sources
+    stat_line: bool,
+    scy: u8,
+    scx: u8,
+    control: Control,
+    wy: u8,
+    wx: u8,
+    // set once LY has matched WY in the current frame
+    wy_triggered: bool,
+    // window row to draw next; advances only on lines where the window was drawn
+    window_line: u8,
+    // the window reached the end of the last line with WX = 166, so it covers this one
+    window_wrap: bool,
+    pub frame_buffer: [u32; SCREEN_PIXELS],
+    pub interrupt_vblank: bool,
+    // set when mode 0 starts on a visible line (HDMA)
+    pub hblank: bool,
+    pub interrupt_lcdc: bool,
+}
+
+bitflags!(
+    struct Control: u8 {
+        const LCD_ENABLE      = 0b_1000_0000;
+        const WINDOW_TILE_MAP = 0b_0100_0000;
+        const WINDOW_ENABLE   = 0b_0010_0000;
+        const BG_WINDOW_TILE  = 0b_0001_0000;
+        const BG_TILE_MAP     = 0b_0000_1000;
+        const OBJ_SIZE        = 0b_0000_0100;
+        const OBJ_ENABLE      = 0b_0000_0010;
+        const BG_ENABLE       = 0b_0000_0001;
+    }
+);
+
+bitflags!(
+    struct Stat: u8 {
+        const LYC_INTERRUPT    = 0b_0100_0000;
+        const OAM_INTERRUPT    = 0b_0010_0000;
+        const VBLANK_INTERRUPT = 0b_0001_0000;
+        const HBLANK_INTERRUPT = 0b_0000_1000;
+        const LYC_FLAG         = 0b_0000_0100;
+        const HBLANK_MODE      = 0b_0000_0000;
+        const VBLANK_MODE      = 0b_0000_0001;
+        const ACCESS_OAM_MODE  = 0b_0000_0010;
+        const ACCESS_VRAM_MODE = 0b_0000_0011;
+    }
+);
+
+#[derive(Debug, Clone)]
+struct Sprite {
+    addr: usize,
+    x: u8,
+    // row in the tile (0-15) after flipping
+    line: usize,
+    tile_number: u8,
+    flags: SpriteFlags,
+}
+
+bitflags!(
+    struct SpriteFlags: u8 {
+        const PRIORITY    = 0b_1000_0000;
+        const FLIP_Y      = 0b_0100_0000;
+        const FLIP_X      = 0b_0010_0000;
+        const PALETTE     = 0b_0001_0000;
+        const BANK        = 0b_0000_1000;
+        const CGB_PALETTE = 0b_0000_0111;
+    }
+);
+
+// CGB BG map attributes (VRAM bank 1)
+bitflags!(
+    struct TileAttributes: u8 {
+        const PRIORITY = 0b_1000_0000;
+        const FLIP_Y   = 0b_0100_0000;
+        const FLIP_X   = 0b_0010_0000;
+        const BANK     = 0b_0000_1000;
+        const PALETTE  = 0b_0000_0111;
+    }
+);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BgPixel {
+    color_num: u8,
+    priority: bool,
+}
+
+// CGB palette RAM: 8 palettes x 4 colors in RGB555, accessed through BCPS/BCPD or OCPS/OCPD
+#[derive(Debug)]
+struct ColorPalette {
+    ram: [u8; PALETTE_RAM_SIZE],
+    index: u8,
+    auto_increment: bool,
+}
+
+impl ColorPalette {
+    fn new() -> ColorPalette {
+        ColorPalette {
+            // white
+            ram: [0xff; PALETTE_RAM_SIZE],
+            index: 0,
+            auto_increment: false,
+        }
+    }
+
+    fn read_spec(&self) -> u8 {
+        (self.auto_increment as u8) << 7 | 0x40 | self.index
+    }
+
+    fn write_spec(&mut self, v: u8) {
+        self.index = v & 0x3f;
+        self.auto_increment = v & 0x80 != 0;
+    }
+
+    fn read_data(&self) -> u8 {
+        self.ram[self.index as usize]
+    }
+
+    fn write_data(&mut self, v: u8) {
+        self.ram[self.index as usize] = v;
+        if self.auto_increment {
+            self.index = (self.index + 1) & 0x3f;
+        }
+    }
+
+    fn color(&self, palette: u8, number: u8) -> u32 {
+        let i = ((palette as usize & 0x07) * 4 + number as usize) * 2;
+        let rgb555 = self.ram[i] as u32 | (self.ram[i + 1] as u32) << 8;
+        let scale = |c: u32| (c << 3) | (c >> 2);
+        let r = scale(rgb555 & 0x1f);
+        let g = scale((rgb555 >> 5) & 0x1f);
+        let b = scale((rgb555 >> 10) & 0x1f);
+        r << 16 | g << 8 | b
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Mode {
+    HBlank,
+    VBlank,
+    AccessOAM,
+    AccessVRAM,
+}
+
+impl PPU {
+    pub fn new(cgb: bool) -> PPU {
+        PPU {
+            cgb,
+            vram: [0; VRAM_BANK_SIZE * VRAM_BANKS],
+            vram_bank: 0,
+            oam: [0; OAM_SIZE],
+            mode: Mode::HBlank,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            palette: Palette::default(),
+            bg_palette: ColorPalette::new(),
+            obj_palette: ColorPalette::new(),
+            obj_priority_x: !cgb,
+            clocks: 0,
+            renderer: Renderer::Scanline,
+            fifo: Fifo::new(),
+            mode3_clocks: 0,
+            ly: 0,
+            lyc: 0,
+            stat: Stat::empty(),
+            stat_line: false,
+            scy: 0,
+            scx: 0,
+            control: Control::empty(),
+            wy: 0,
+            wx: 0,
+            wy_triggered: false,
+            window_line: 0,
+            window_wrap: false,
+            frame_buffer: [0; SCREEN_PIXELS],
+            interrupt_vblank: false,
+            hblank: false,
+            interrupt_lcdc: false,
+        }
+    }
+
+    fn reset_window(&mut self) {
+        self.wy_triggered = false;
+        self.window_line = 0;
+        self.window_wrap = false;
+    }
+
+    // screen x where the window starts; WX < 7 starts it left of the screen.
+    // WX = 166 shows the window's first column at x = 159, and a hardware bug makes it
+    // start at x = 0 on the following line
+    fn window_x(&self) -> i16 {
+        if self.window_wrap {
+            0
+        } else {
+            self.wx as i16 - 7
+        }
+    }
+
+    fn mode_bits(&self) -> Stat {
+        if !self.control.contains(Control::LCD_ENABLE) {
+            return Stat::HBLANK_MODE;
+        }
+        match self.mode {
+            Mode::HBlank => Stat::HBLANK_MODE,
+            Mode::VBlank => Stat::VBLANK_MODE,
+            Mode::AccessOAM => Stat::ACCESS_OAM_MODE,
+            Mode::AccessVRAM => Stat::ACCESS_VRAM_MODE,
+        }
+    }
+
+    // the enabled sources are ORed into one line and LCD_STAT is requested on its rising edge only,
+    // so e.g. the mode 2 interrupt is blocked right after a mode 0 interrupt
+    fn update_stat(&mut self) {
+        self.stat.set(Stat::LYC_FLAG, self.ly == self.lyc);
+
+        let mode_line = match self.mode {
+            Mode::HBlank => self.stat.contains(Stat::HBLANK_INTERRUPT),
+            Mode::VBlank => self.stat.contains(Stat::VBLANK_INTERRUPT),
+            Mode::AccessOAM => self.stat.contains(Stat::OAM_INTERRUPT),
+            Mode::AccessVRAM => false,
+        };
+        let lyc_line = self.stat.contains(Stat::LYC_INTERRUPT | Stat::LYC_FLAG);
+        let line = self.control.contains(Control::LCD_ENABLE) && (mode_line || lyc_line);
+
+        if line && !self.stat_line {
+            self.interrupt_lcdc = true;
+        }
+        self.stat_line = line;
+    }
+
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.renderer = renderer;
+    }
+
+    pub fn renderer(&self) -> Renderer {
+        self.renderer
+    }
+
+    pub fn run(&mut self, tick: u32) {
+        if !self.control.contains(Control::LCD_ENABLE) {
+            return;
+        }
+        self.clocks += tick;
+
+        loop {
+            match self.mode {
+                Mode::AccessOAM => {
+                    if self.clocks < 80 {
+                        break;
+                    }
+                    self.clocks -= 80;
+                    self.mode = Mode::AccessVRAM;
+                    self.mode3_clocks = 0;
+                    self.update_stat();
+                    if self.ly == self.wy {
+                        self.wy_triggered = true;
+                    }
+                    // a renderer change takes effect from the next line
+                    match self.renderer {
+                        Renderer::Scanline => self.render_line(),
+                        Renderer::Fifo => self.start_fifo_line(),
+                    }
+                }
+                Mode::AccessVRAM => {
+                    if self.fifo.is_active() {
+                        while self.clocks > 0 && self.fifo.is_active() {
+                            self.clocks -= 1;
+                            self.mode3_clocks += 1;
+                            self.step_fifo();
+                        }
+                        if self.fifo.is_active() {
+                            break;
+                        }
+                    } else {
+                        if self.clocks < 172 {
+                            break;
+                        }
+                        self.clocks -= 172;
+                        self.mode3_clocks = 172;
+                    }
+                    self.mode = Mode::HBlank;
+                    self.hblank = true;
+                    self.update_stat();
+                }
+                Mode::HBlank => {
+                    let hblank_clocks = LINE_CLOCKS_AFTER_OAM.saturating_sub(self.mode3_clocks);
+                    if self.clocks < hblank_clocks {
+                        break;
+                    }
+                    self.clocks -= hblank_clocks;
+                    self.ly = self.ly.wrapping_add(1);
+
+                    if self.ly >= SCREEN_HEIGHT as u8 {
+                        self.mode = Mode::VBlank;
+                        self.interrupt_vblank = true;
+                    } else {
+                        self.mode = Mode::AccessOAM;
+                    }
+
+                    self.update_stat();
+                }
+                Mode::VBlank => {
+                    if self.clocks < 456 {
+                        break;
+                    }
+                    self.clocks -= 456;
+                    self.ly = self.ly.wrapping_add(1);
+
+                    if self.ly >= SCREEN_HEIGHT as u8 + 10 {
+                        self.mode = Mode::AccessOAM;
+                        self.ly = 0;
+                        self.reset_window();
+                    }
+
+                    self.update_stat();
+                }
+            }
+        }
+    }
+
+    pub fn read_byte(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0x9fff => {
+                if self.mode == Mode::AccessVRAM {
+                    return 0xff;
+                }
+                self.vram[self.vram_bank * VRAM_BANK_SIZE + (addr & 0x1fff) as usize]
+            }
+            0xfe00..=0xfe9f => {
+                if self.mode != Mode::HBlank && self.mode != Mode::VBlank {
+                    return 0xff;
+                }
+                self.oam[(addr & (OAM_SIZE as u16 - 1)) as usize]
+            }
+            0xff40 => self.control.bits,
+            0xff41 => 0x80 | self.stat.bits | self.mode_bits().bits,
+            0xff42 => self.scy,
+            0xff43 => self.scx,
+            0xff44 => self.ly,
+            0xff45 => self.lyc,
+            0xff47 => self.bgp,
+            0xff48 => self.obp0,
+            0xff49 => self.obp1,
+            0xff4a => self.wy,
+            0xff4b => self.wx,
+            0xff4f if self.cgb => 0xfe | self.vram_bank as u8,
+            0xff68 if self.cgb => self.bg_palette.read_spec(),
+            0xff6a if self.cgb => self.obj_palette.read_spec(),
+            // palette RAM is inaccessible while the LCD reads it
+            0xff69 | 0xff6b if self.cgb && self.mode == Mode::AccessVRAM => 0xff,
+            0xff69 if self.cgb => self.bg_palette.read_data(),
+            0xff6b if self.cgb => self.obj_palette.read_data(),
+            0xff6c if self.cgb => 0xfe | self.obj_priority_x as u8,
+            _ => 0xff,
+        }
+    }
+
+    pub fn write_byte(&mut self, addr: u16, v: u8) {
+        match addr {
+            0x8000..=0x9fff => {
+                /*if self.mode == Mode::AccessVRAM {
+                    return;
+                }*/
+                self.vram[self.vram_bank * VRAM_BANK_SIZE + (addr & 0x1fff) as usize] = v;
+            }
+            0xfe00..=0xfe9f => {
+                if self.mode != Mode::HBlank && self.mode != Mode::VBlank {
+                    return;
+                }
+                self.oam[(addr & 0x00ff) as usize] = v;
+            }
+            0xff40 => {
+                let val = Control::from_bits_truncate(v);
+                let toggled =
+                    self.control.contains(Control::LCD_ENABLE) != val.contains(Control::LCD_ENABLE);
+                self.control = val;
+                if toggled {
+                    self.ly = 0;
+                    self.clocks = 0;
+                    self.reset_window();
+                    self.mode = if val.contains(Control::LCD_ENABLE) {
+                        Mode::AccessOAM
+                    } else {
+                        Mode::HBlank
+                    };
+                    self.update_stat();
+                }
+            }
+            0xff41 => {
+                // mode and LYC flag are read only
+                self.stat =
+                    Stat::from_bits_truncate(v & 0b_0111_1000) | (self.stat & Stat::LYC_FLAG);
+                self.update_stat();
+            }
+            0xff42 => self.scy = v,
+            0xff43 => self.scx = v,
+            0xff44 => (), // read only
+            0xff45 if self.lyc != v => {
+                self.lyc = v;
+                self.update_stat();
+            }
+            0xff47 => self.bgp = v,
+            0xff48 => self.obp0 = v,
+            0xff49 => self.obp1 = v,
+            0xff4a => self.wy = v,
+            0xff4b => self.wx = v,
+            0xff4f if self.cgb => self.vram_bank = (v & 0x01) as usize,
+            0xff68 if self.cgb => self.bg_palette.write_spec(v),
+            0xff69 if self.cgb => self.bg_palette.write_data(v),
+            0xff6a if self.cgb => self.obj_palette.write_spec(v),
+            0xff6b if self.cgb => self.obj_palette.write_data(v),
+            0xff6c if self.cgb => self.obj_priority_x = v & 0x01 != 0,
+            _ => (),
+        }
+    }
+
+    pub fn is_lcd_on(&self) -> bool {
+        self.control.contains(Control::LCD_ENABLE)
+    }
+
+    // only used in DMG mode
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    fn tile_addr(control: Control, number: u8) -> usize {
+        if control.contains(Control::BG_WINDOW_TILE) {
+            // block0: 0x0000-0x07ff block1: 0x0800-0x0fff
+            (((number as i8 as i16) << 4) as u16 & 0x0fff) as usize
+        } else {
+            // block2: 0x1000-0x17ff block1: 0x0800-0x0fff
+            0x1000_u16.wrapping_add(((number as i8 as i16) << 4) as u16) as usize
+        }
+    }
+
+    // color number (0-3) of pixel x (0 = leftmost) on a row of a tile
+    fn tile_pixel(&self, bank: usize, tile_addr: usize, line: usize, x: u8) -> u8 {
+        let addr = bank * VRAM_BANK_SIZE + ((tile_addr | (line * 2)) & 0x1fff);
+        let lo = self.vram[addr];
+        let hi = self.vram[addr + 1];
+        let bit = 7 - (x & 0x07);
+        (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1)
+    }
+
+    // tile data address and attributes of the tile at (x, y) of a 256x256 tile map
+    fn map_tile(&self, tile_map_addr_base: usize, x: u8, y: u8) -> (usize, TileAttributes) {
+        let map_addr = (((y / 8) as usize * 32 + (x / 8) as usize) | tile_map_addr_base) & 0x1fff;
+        let tile_number = self.vram[map_addr];
+        let attributes = if self.cgb {
+            TileAttributes::from_bits_truncate(self.vram[VRAM_BANK_SIZE + map_addr])
+        } else {
+            TileAttributes::empty()
+        };
+        (PPU::tile_addr(self.control, tile_number), attributes)
+    }
+
+    // BG/window pixel at (x, y) of a 256x256 tile map
+    fn map_pixel(&self, tile_map_addr_base: usize, x: u8, y: u8) -> (u8, TileAttributes) {
+        let (tile_addr, attributes) = self.map_tile(tile_map_addr_base, x, y);
+        let line = if attributes.contains(TileAttributes::FLIP_Y) {
+            7 - (y & 0x07)
+        } else {
+            y & 0x07
+        } as usize;
+        let x = if attributes.contains(TileAttributes::FLIP_X) {
+            7 - (x & 0x07)
+        } else {
+            x & 0x07
+        };
+        let bank = attributes.contains(TileAttributes::BANK) as usize;
+        (self.tile_pixel(bank, tile_addr, line, x), attributes)
+    }
+
+    fn bg_tile_map(&self) -> usize {
+        if self.control.contains(Control::BG_TILE_MAP) {
+            0x1c00 // 0x1c00-0x1fff
+        } else {
+            0x1800 // 0x1800-0x1bff
+        }
+    }
+
+    fn window_tile_map(&self) -> usize {
+        if self.control.contains(Control::WINDOW_TILE_MAP) {
+            0x1c00 // 0x1c00-0x1fff
+        } else {
+            0x1800 // 0x1800-0x1bff
+        }
+    }
+
+    fn bg_color(&self, attributes: TileAttributes, color_num: u8) -> u32 {
+        if self.cgb {
+            let palette = (attributes & TileAttributes::PALETTE).bits;
+            self.bg_palette.color(palette, color_num)
+        } else {
+            palette::shade(&self.palette.bg, self.bgp, color_num)
+        }
+    }
+
+    fn obj_color(&self, flags: SpriteFlags, color_num: u8) -> u32 {
+        if self.cgb {
+            let palette = (flags & SpriteFlags::CGB_PALETTE).bits;
+            self.obj_palette.color(palette, color_num)
+        } else if flags.contains(SpriteFlags::PALETTE) {
+            palette::shade(&self.palette.obj1, self.obp1, color_num)
+        } else {
+            palette::shade(&self.palette.obj0, self.obp0, color_num)
+        }
+    }
+
+    fn render_line(&mut self) {
+        let mut pixels = [self.palette.bg[0]; SCREEN_WIDTH];
+        let mut bg = [BgPixel {
+            color_num: 0,
+            priority: false,
+        }; SCREEN_WIDTH];
+
+        // on CGB, LCDC bit 0 only takes the priority away from BG and window
+        let bg_enable = self.cgb || self.control.contains(Control::BG_ENABLE);
+
+        if bg_enable {
+            let tile_map_addr_base = self.bg_tile_map();
+
+            let y = self.ly.wrapping_add(self.scy);
+            for i in 0..SCREEN_WIDTH as u8 {
+                let x = i.wrapping_add(self.scx);
+                let (color_num, attributes) = self.map_pixel(tile_map_addr_base, x, y);
+
+                bg[i as usize] = BgPixel {
+                    color_num,
+                    priority: attributes.contains(TileAttributes::PRIORITY),
+                };
+                pixels[i as usize] = self.bg_color(attributes, color_num);
+            }
+        }
+
+        let wx = self.window_x();
+        let window = bg_enable
+            && self.control.contains(Control::WINDOW_ENABLE)
+            && self.wy_triggered
+            && wx < SCREEN_WIDTH as i16;
+        self.window_wrap = window && self.wx == 166;
+        if window {
+            let tile_map_addr_base = self.window_tile_map();
+
+            let y = self.window_line;
+            self.window_line = self.window_line.wrapping_add(1);
+            for i in wx.max(0) as u8..SCREEN_WIDTH as u8 {
+                let x = (i as i16 - wx) as u8;
+                let (color_num, attributes) = self.map_pixel(tile_map_addr_base, x, y);
+
+                bg[i as usize] = BgPixel {
+                    color_num,
+                    priority: attributes.contains(TileAttributes::PRIORITY),
+                };
+                pixels[i as usize] = self.bg_color(attributes, color_num);
+            }
+        }
+
+        if self.control.contains(Control::OBJ_ENABLE) {
+            self.render_sprites(&mut pixels, &bg);
+        }
+
+        let start = SCREEN_WIDTH * (self.ly as usize);
+        self.frame_buffer[start..start + SCREEN_WIDTH].copy_from_slice(&pixels);
+    }
+
+    // sprites on the current line in OAM order (10 at most, off-screen ones included)
+    fn scan_oam(&self) -> Vec<Sprite> {
+        let size: u8 = if self.control.contains(Control::OBJ_SIZE) {
+            16
+        } else {
+            8
+        };
+
+        let mut sprites: Vec<Sprite> = Vec::with_capacity(10);
+        for i in 0..(OAM_SIZE / 4) {
+            let addr = i << 2;
+            let y = self.oam[addr].wrapping_sub(16);
+            let line = self.ly.wrapping_sub(y);
+            if line > size - 1 {
+                // not on line
+                continue;
+            }
+
+            let flags = SpriteFlags::from_bits_truncate(self.oam[addr + 3]);
+            let line = if flags.contains(SpriteFlags::FLIP_Y) {
+                size - 1 - line
+            } else {
+                line
+            } as usize;
+            let tile_number = if size == 16 {
+                self.oam[addr + 2] & 0xfe
+            } else {
+                self.oam[addr + 2]
+            };
+
+            sprites.push(Sprite {
+                addr,
+                x: self.oam[addr + 1],
+                line,
+                tile_number,
+                flags,
+            });
+            if sprites.len() == 10 {
+                break;
+            }
+        }
+        sprites
+    }
+
+    // color number of pixel i (0 = leftmost on screen) of a sprite
+    fn sprite_pixel(&self, sprite: &Sprite, i: u8) -> u8 {
+        let bank = (self.cgb && sprite.flags.contains(SpriteFlags::BANK)) as usize;
+        let tile_addr = (sprite.tile_number as usize) << 4;
+        let x = if sprite.flags.contains(SpriteFlags::FLIP_X) {
+            7 - i
+        } else {
+            i
+        };
+        self.tile_pixel(bank, tile_addr, sprite.line, x)
+    }
+
+    // whether an opaque sprite pixel is drawn behind the BG
+    fn obj_hidden(&self, bg: BgPixel, flags: SpriteFlags) -> bool {
+        bg.color_num != 0
+            && if self.cgb {
+                self.control.contains(Control::BG_ENABLE)
+                    && (bg.priority || flags.contains(SpriteFlags::PRIORITY))
+            } else {
+                flags.contains(SpriteFlags::PRIORITY)
+            }
+    }
+
+    fn render_sprites(&self, pixels: &mut [u32; SCREEN_WIDTH], bg: &[BgPixel; SCREEN_WIDTH]) {
+        let mut sprites = self.scan_oam();
+
+        // sprites priority: DMG prefers low x then low index, CGB the low index only
+        if self.obj_priority_x {
+            sprites.sort_by(|a, b| match a.x.cmp(&b.x) {
+                Ordering::Equal => a.addr.cmp(&b.addr),
+                other => other,
+            });
+        }
+
+        // the sprite with the highest priority owns the pixel even when the BG hides it
+        let mut owned = [false; SCREEN_WIDTH];
+        for sprite in sprites.iter() {
+            for i in 0..8u8 {
+                let target = sprite.x as usize + i as usize;
+                if !(8..SCREEN_WIDTH + 8).contains(&target) || owned[target - 8] {
+                    continue;
+                }
+                let target = target - 8;
+
+                let color_num = self.sprite_pixel(sprite, i);
+                if color_num == 0 {
+                    continue;
+                }
+                owned[target] = true;
+
+                if !self.obj_hidden(bg[target], sprite.flags) {
+                    pixels[target] = self.obj_color(sprite.flags, color_num);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+// RGB colors of the four DMG shades, from the lightest to the darkest
+pub type Shades = [u32; 4];
+
+// colors used for BGP, OBP0 and OBP1 in DMG mode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub bg: Shades,
+    pub obj0: Shades,
+    pub obj1: Shades,
+}
+
+impl Palette {
+    pub const GRAY: Palette = Palette::uniform([0xffffff, 0xaaaaaa, 0x555555, 0x000000]);
+    // the green LCD of the original Game Boy
+    pub const GREEN: Palette = Palette::uniform([0x9bbc0f, 0x8bac0f, 0x306230, 0x0f380f]);
+    pub const POCKET: Palette = Palette::uniform([0xc4cfa1, 0x8b956d, 0x4d533c, 0x1f1f1f]);
+    // what the CGB boot ROM picks for DMG games it doesn't know
+    pub const CGB: Palette = Palette {
+        bg: [0xffffff, 0x7bff31, 0x0063c5, 0x000000],
+        obj0: [0xffffff, 0xff8484, 0x943a3a, 0x000000],
+        obj1: [0xffffff, 0xff8484, 0x943a3a, 0x000000],
+    };
+
+    // the same shades for BG and both OBJ palettes
+    pub const fn uniform(shades: Shades) -> Palette {
+        Palette {
+            bg: shades,
+            obj0: shades,
+            obj1: shades,
+        }
+    }
+
+    // applies a gamma curve to every channel, e.g. > 1.0 darkens the midtones the way
+    // the DMG's LCD shows them
+    pub fn with_gamma(&self, gamma: f64) -> Palette {
+        let correct = |shades: Shades| shades.map(|c| gamma_correct(c, gamma));
+        Palette {
+            bg: correct(self.bg),
+            obj0: correct(self.obj0),
+            obj1: correct(self.obj1),
+        }
+    }
+}
+
+fn gamma_correct(color: u32, gamma: f64) -> u32 {
+    [16, 8, 0].iter().fold(0, |rgb, shift| {
+        let c = ((color >> shift) & 0xff) as f64 / 255.0;
+        rgb | ((c.powf(gamma) * 255.0).round() as u32) << shift
+    })
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::GRAY
+    }
+}
+
+// color of a color number mapped through a palette register
+pub fn shade(shades: &Shades, register: u8, number: u8) -> u32 {
+    shades[((register >> (number << 1)) & 0b_0011) as usize]
+}
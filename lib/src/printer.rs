@@ -0,0 +1,291 @@
+use std::collections::VecDeque;
+
+use crate::serial::SerialDevice;
+
+pub const PRINTOUT_WIDTH: usize = 160;
+
+const TILES_PER_ROW: usize = PRINTOUT_WIDTH / 8;
+const TILE_BYTES: usize = 16;
+// 9 bands of 2 tile rows, one screen
+const IMAGE_BUFFER_SIZE: usize = 0x2280;
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+// first byte of the reply while the GB sends the two bytes after the checksum
+const ALIVE: u8 = 0x81;
+
+const COMMAND_INIT: u8 = 0x01;
+const COMMAND_PRINT: u8 = 0x02;
+const COMMAND_DATA: u8 = 0x04;
+const COMMAND_STATUS: u8 = 0x0f;
+
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_BUSY: u8 = 0x02;
+const STATUS_IMAGE_FULL: u8 = 0x04;
+const STATUS_UNPROCESSED: u8 = 0x08;
+const STATUS_PACKET_ERROR: u8 = 0x10;
+
+// status packets that report busy after a print; games wait for the printer to finish
+const BUSY_POLLS: u8 = 4;
+
+// a finished sheet of paper
+#[derive(Debug, Clone, PartialEq)]
+pub struct Printout {
+    pub height: usize,
+    // shades from 0 (white) to 3 (black), PRINTOUT_WIDTH pixels per row
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Magic(usize),
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+// Game Boy Printer; always clocked by the Game Boy
+#[derive(Debug)]
+pub struct Printer {
+    state: State,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+    status: u8,
+    busy_polls: u8,
+    // 2bpp tiles received since the last print
+    image: Vec<u8>,
+    // the sheet being printed, continued until a print has a bottom margin
+    pixels: Vec<u8>,
+    printouts: VecDeque<Printout>,
+}
+
+impl Printer {
+    pub fn new() -> Printer {
+        Printer {
+            state: State::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+            status: 0,
+            busy_polls: 0,
+            image: Vec::with_capacity(IMAGE_BUFFER_SIZE),
+            pixels: Vec::new(),
+            printouts: VecDeque::new(),
+        }
+    }
+
+    pub fn take_printout(&mut self) -> Option<Printout> {
+        self.printouts.pop_front()
+    }
+
+    fn add_checksum(&mut self, v: u8) {
+        self.checksum = self.checksum.wrapping_add(v as u16);
+    }
+
+    fn handle_packet(&mut self) {
+        if self.checksum != self.received_checksum {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+
+        match self.command {
+            COMMAND_INIT => {
+                // a new job; the sheet of the previous one is cut off
+                self.feed();
+                self.image.clear();
+                self.status = 0;
+                self.busy_polls = 0;
+            }
+            COMMAND_PRINT if self.data.len() == 4 => {
+                let margins = self.data[1];
+                let palette = self.data[2];
+                if margins >> 4 != 0 {
+                    self.feed();
+                }
+                // 0 sheets only feeds the paper
+                if self.data[0] != 0 {
+                    self.print(palette);
+                }
+                if margins & 0x0f != 0 {
+                    self.feed();
+                }
+                self.status &= !(STATUS_UNPROCESSED | STATUS_IMAGE_FULL);
+                self.busy_polls = BUSY_POLLS;
+            }
+            // an empty data packet marks the end of the image
+            COMMAND_DATA => {
+                let data = if self.compressed {
+                    decompress(&self.data)
+                } else {
+                    std::mem::take(&mut self.data)
+                };
+                let n = data.len().min(IMAGE_BUFFER_SIZE - self.image.len());
+                self.image.extend_from_slice(&data[..n]);
+                if self.image.len() == IMAGE_BUFFER_SIZE {
+                    self.status |= STATUS_IMAGE_FULL;
+                }
+                if !self.image.is_empty() {
+                    self.status |= STATUS_UNPROCESSED;
+                }
+            }
+            COMMAND_STATUS => (),
+            _ => self.status |= STATUS_PACKET_ERROR,
+        }
+    }
+
+    // decodes the tiles with the palette and appends them to the sheet
+    fn print(&mut self, palette: u8) {
+        // 0x00 is commonly sent meaning the default palette
+        let palette = if palette == 0 { 0xe4 } else { palette };
+        let rows = self.image.len() / (TILE_BYTES * TILES_PER_ROW);
+        for row in 0..rows {
+            for y in 0..8 {
+                for x in 0..PRINTOUT_WIDTH {
+                    let tile = row * TILES_PER_ROW + x / 8;
+                    let addr = tile * TILE_BYTES + y * 2;
+                    let bit = 7 - (x % 8);
+                    let lo = (self.image[addr] >> bit) & 1;
+                    let hi = (self.image[addr + 1] >> bit) & 1;
+                    let color = (hi << 1) | lo;
+                    self.pixels.push((palette >> (color << 1)) & 0b_0011);
+                }
+            }
+        }
+        self.image.clear();
+    }
+
+    // the paper is cut off at a margin
+    fn feed(&mut self) {
+        if self.pixels.is_empty() {
+            return;
+        }
+        let pixels = std::mem::take(&mut self.pixels);
+        self.printouts.push_back(Printout {
+            height: pixels.len() / PRINTOUT_WIDTH,
+            pixels,
+        });
+    }
+
+    fn reply_status(&mut self) -> u8 {
+        let mut status = self.status;
+        if self.busy_polls > 0 {
+            self.busy_polls -= 1;
+            status |= STATUS_BUSY;
+        }
+        status
+    }
+}
+
+impl Default for Printer {
+    fn default() -> Printer {
+        Printer::new()
+    }
+}
+
+impl SerialDevice for Printer {
+    fn exchange(&mut self, data: u8) -> u8 {
+        let mut reply = 0x00;
+        self.state = match self.state {
+            State::Magic(i) if data == MAGIC[i] => {
+                if i + 1 == MAGIC.len() {
+                    State::Command
+                } else {
+                    State::Magic(i + 1)
+                }
+            }
+            State::Magic(_) if data == MAGIC[0] => State::Magic(1),
+            State::Magic(_) => State::Magic(0),
+            State::Command => {
+                self.command = data;
+                self.checksum = 0;
+                self.add_checksum(data);
+                State::Compression
+            }
+            State::Compression => {
+                self.compressed = data & 0x01 != 0;
+                self.add_checksum(data);
+                State::LengthLow
+            }
+            State::LengthLow => {
+                self.length = data as u16;
+                self.add_checksum(data);
+                State::LengthHigh
+            }
+            State::LengthHigh => {
+                self.length |= (data as u16) << 8;
+                self.add_checksum(data);
+                self.data.clear();
+                if self.length == 0 {
+                    State::ChecksumLow
+                } else {
+                    State::Data
+                }
+            }
+            State::Data => {
+                self.data.push(data);
+                self.add_checksum(data);
+                if self.data.len() == self.length as usize {
+                    State::ChecksumLow
+                } else {
+                    State::Data
+                }
+            }
+            State::ChecksumLow => {
+                self.received_checksum = data as u16;
+                State::ChecksumHigh
+            }
+            State::ChecksumHigh => {
+                self.received_checksum |= (data as u16) << 8;
+                self.handle_packet();
+                State::Alive
+            }
+            State::Alive => {
+                reply = ALIVE;
+                State::Status
+            }
+            State::Status => {
+                reply = self.reply_status();
+                State::Magic(0)
+            }
+        };
+        reply
+    }
+
+    fn flush(&mut self) {
+        self.feed();
+    }
+}
+
+// RLE: 0x80 | (n - 2) followed by a byte repeated n times, or n - 1 followed by n literal bytes
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() * 2);
+    let mut i = 0;
+    while i < data.len() {
+        let n = data[i];
+        i += 1;
+        if n & 0x80 != 0 {
+            if let Some(&b) = data.get(i) {
+                out.resize(out.len() + (n & 0x7f) as usize + 2, b);
+            }
+            i += 1;
+        } else {
+            let end = (i + n as usize + 1).min(data.len());
+            out.extend_from_slice(&data[i..end]);
+            i = end;
+        }
+    }
+    out
+}
@@ -1,9 +1,77 @@
 use bitflags::bitflags;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+// 8192 Hz, or 262144 Hz with the CGB fast clock
+const BIT_CLOCKS: u32 = 512;
+const FAST_BIT_CLOCKS: u32 = 16;
+
+// what is plugged into the link port
+pub trait SerialDevice: fmt::Debug + Send {
+    // called when a transfer clocked by the Game Boy completes; returns the byte shifted in
+    fn exchange(&mut self, data: u8) -> u8;
+    // called while the Game Boy waits for an external clock; returns the byte shifted in
+    // once the other side has clocked a transfer
+    fn poll_external(&mut self, _data: u8) -> Option<u8> {
+        None
+    }
+    // called on every step with the CPU ticks it took, transferring or not, for devices that
+    // keep time with the emulation
+    fn run(&mut self, _ticks: u32) {}
+    // called when SC is written, which restarts or cancels a transfer waiting for the external clock
+    fn control_written(&mut self) {}
+    // called when the emulator exits, to write out anything the device still holds
+    fn flush(&mut self) {}
+}
+
+// nothing connected: the data line is pulled up
+#[derive(Debug)]
+struct Disconnected;
+
+impl SerialDevice for Disconnected {
+    fn exchange(&mut self, _data: u8) -> u8 {
+        0xff
+    }
+}
+
+// records the bytes sent by the Game Boy, like test ROMs printing their results;
+// clones share the record, so a clone can be kept to read it
+#[derive(Debug, Clone, Default)]
+pub struct SerialCapture {
+    bytes: Arc<Mutex<Vec<u8>>>,
+}
+
+impl SerialCapture {
+    pub fn new() -> SerialCapture {
+        SerialCapture::default()
+    }
+
+    pub fn output(&self) -> String {
+        String::from_utf8_lossy(&self.bytes.lock().unwrap()).into_owned()
+    }
+
+    // the raw bytes since the last call; a multi-byte character may be split between calls
+    pub fn take_output(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.bytes.lock().unwrap())
+    }
+}
+
+impl SerialDevice for SerialCapture {
+    fn exchange(&mut self, data: u8) -> u8 {
+        self.bytes.lock().unwrap().push(data);
+        0xff
+    }
+}
 
 #[derive(Debug)]
 pub struct Serial {
+    cgb: bool,
     data: u8,
     control: Control,
+    device: Box<dyn SerialDevice>,
+    // CPU ticks left until the transfer completes
+    clocks: u32,
+    pub interrupt: bool,
 }
 
 bitflags!(
@@ -15,17 +83,27 @@ bitflags!(
 );
 
 impl Serial {
-    pub fn new() -> Serial {
+    pub fn new(cgb: bool) -> Serial {
         Serial {
+            cgb,
             data: 0,
             control: Control::empty(),
+            device: Box::new(Disconnected),
+            clocks: 0,
+            interrupt: false,
         }
     }
 
+    pub fn set_device(&mut self, device: Box<dyn SerialDevice>) {
+        self.device = device;
+    }
+
     pub fn read_byte(&self, addr: u16) -> u8 {
         match addr {
             0xff01 => self.data,
-            0xff02 => self.control.bits,
+            // the clock speed bit only exists on CGB
+            0xff02 if self.cgb => 0x7c | self.control.bits,
+            0xff02 => 0x7e | self.control.bits,
             _ => unimplemented!("read: Serial I/O: {:04x}", addr),
         }
     }
@@ -33,8 +111,48 @@ impl Serial {
     pub fn write_byte(&mut self, addr: u16, v: u8) {
         match addr {
             0xff01 => self.data = v,
-            0xff02 => self.control = Control::from_bits_truncate(v),
+            0xff02 => {
+                self.control = Control::from_bits_truncate(v);
+                if !self.cgb {
+                    self.control.remove(Control::FAST_CLOCK_SPEED);
+                }
+                let bit_clocks = if self.control.contains(Control::FAST_CLOCK_SPEED) {
+                    FAST_BIT_CLOCKS
+                } else {
+                    BIT_CLOCKS
+                };
+                self.clocks = bit_clocks * 8;
+                self.device.control_written();
+            }
             _ => unimplemented!("write: Serial I/O: {:04x} {:02x}", addr, v),
         }
     }
+
+    pub fn flush(&mut self) {
+        self.device.flush();
+    }
+
+    // ticks of the CPU clock, so transfers are twice as fast in double speed mode
+    pub fn run(&mut self, ticks: u32) {
+        self.device.run(ticks);
+        if !self.control.contains(Control::START) {
+            return;
+        }
+        if self.control.contains(Control::INTERNAL_CLOCK) {
+            self.clocks = self.clocks.saturating_sub(ticks);
+            if self.clocks == 0 {
+                self.data = self.device.exchange(self.data);
+                self.complete();
+            }
+        } else if let Some(v) = self.device.poll_external(self.data) {
+            // the other side drives the clock, so there's nothing to count
+            self.data = v;
+            self.complete();
+        }
+    }
+
+    fn complete(&mut self) {
+        self.control.remove(Control::START);
+        self.interrupt = true;
+    }
 }
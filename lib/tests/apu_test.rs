@@ -0,0 +1,170 @@
+#[cfg(test)]
+extern crate rstest;
+extern crate speculate;
+
+use rstest::*;
+use speculate::speculate;
+
+use gameboy_rs_lib::CPU;
+
+const SECOND: u32 = 4 * 1024 * 1024;
+
+fn cpu() -> CPU {
+    let mut cpu = CPU::new(vec![0; 0x8000]).unwrap();
+    cpu.init();
+    cpu.mmu.apu.drain_samples(&mut vec![0; 1 << 20]);
+    cpu
+}
+
+speculate! {
+    describe "サンプル生成" {
+        #[rstest(rate,
+            case(44100),
+            case(48000),
+            case(32768),
+        )]
+        fn 指定したレートでステレオのサンプルが生成される(rate: u32) {
+            let mut cpu = cpu();
+            cpu.mmu.apu.set_sample_rate(rate);
+            cpu.mmu.run(SECOND / 4);
+
+            let mut buffer = vec![0; rate as usize];
+            let n = cpu.mmu.apu.drain_samples(&mut buffer);
+            assert!((n as i64 - (rate / 2) as i64).abs() <= 2);
+            assert_eq!(0, cpu.mmu.apu.samples_len());
+        }
+
+        it "矩形波チャンネルの出力がミックスされる" {
+            let mut cpu = cpu();
+            cpu.mmu.write_byte(0xff24, 0x77);
+            cpu.mmu.write_byte(0xff25, 0x01); // channel 1: right only
+            cpu.mmu.write_byte(0xff11, 0x80); // duty 50%
+            cpu.mmu.write_byte(0xff12, 0xf0);
+            cpu.mmu.write_byte(0xff13, 0x00);
+            cpu.mmu.write_byte(0xff14, 0x87); // trigger, 1 kHz
+            cpu.mmu.run(SECOND / 10);
+
+            let mut buffer = vec![0; 8820];
+            let n = cpu.mmu.apu.drain_samples(&mut buffer);
+            let left = buffer[..n].iter().step_by(2).map(|s| (*s as i32).abs()).max();
+            let right = buffer[..n].iter().skip(1).step_by(2).map(|s| (*s as i32).abs()).max();
+            assert_eq!(Some(0), left);
+            assert!(right.unwrap() > 4096);
+        }
+
+        it "電源がオフの間は無音になる" {
+            let mut cpu = cpu();
+            cpu.mmu.write_byte(0xff14, 0x87);
+            cpu.mmu.write_byte(0xff26, 0x00);
+            cpu.mmu.run(SECOND / 10);
+
+            let mut buffer = vec![0; 8820];
+            let n = cpu.mmu.apu.drain_samples(&mut buffer);
+            assert!(buffer[..n].iter().all(|s| *s == 0));
+            assert_eq!(0x70, cpu.mmu.read_byte(0xff26));
+        }
+    }
+
+    describe "フレームシーケンサ" {
+        it "長さカウンタが0になるとチャンネルが止まる" {
+            let mut cpu = cpu();
+            cpu.mmu.write_byte(0xff21, 0xf0);
+            cpu.mmu.write_byte(0xff20, 0x3f); // length 1
+            cpu.mmu.write_byte(0xff23, 0xc0); // trigger with length
+            assert_eq!(0x08, cpu.mmu.read_byte(0xff26) & 0x08);
+
+            cpu.mmu.run(SECOND / 256 * 2);
+            assert_eq!(0x00, cpu.mmu.read_byte(0xff26) & 0x08);
+        }
+
+        it "スイープのオーバーフローでチャンネルが止まる" {
+            let mut cpu = cpu();
+            cpu.mmu.write_byte(0xff10, 0x11); // period 1, increase, shift 1
+            cpu.mmu.write_byte(0xff12, 0xf0);
+            cpu.mmu.write_byte(0xff13, 0x00);
+            cpu.mmu.write_byte(0xff14, 0x84); // frequency 0x400
+            assert_eq!(0x01, cpu.mmu.read_byte(0xff26) & 0x01);
+
+            cpu.mmu.run(SECOND / 128 * 2);
+            assert_eq!(0x00, cpu.mmu.read_byte(0xff26) & 0x01);
+        }
+
+        it "エンベロープで音量が減衰する" {
+            let mut cpu = cpu();
+            cpu.mmu.write_byte(0xff25, 0x22);
+            cpu.mmu.write_byte(0xff16, 0x80);
+            cpu.mmu.write_byte(0xff17, 0x11); // volume 1, decrease
+            cpu.mmu.write_byte(0xff18, 0x00);
+            cpu.mmu.write_byte(0xff19, 0x87);
+            cpu.mmu.run(SECOND / 64 * 2);
+            cpu.mmu.apu.drain_samples(&mut vec![0; 1 << 20]);
+
+            // DC from the DAC fades away through the high-pass filter
+            cpu.mmu.run(SECOND / 10);
+            let mut buffer = vec![0; 8820];
+            let n = cpu.mmu.apu.drain_samples(&mut buffer);
+            assert!(buffer[..n].iter().skip(n / 2).all(|s| (*s as i32).abs() < 64));
+        }
+    }
+
+    describe "波形メモリ" {
+        it "停止中は波形メモリを読み書きできる" {
+            let mut cpu = cpu();
+            for i in 0..16 {
+                cpu.mmu.write_byte(0xff30 + i, i as u8 * 0x11);
+            }
+            assert_eq!(0x55, cpu.mmu.read_byte(0xff35));
+            cpu.mmu.write_byte(0xff26, 0x00);
+            assert_eq!(0xff, cpu.mmu.read_byte(0xff3f));
+            // the APU being powered off doesn't affect wave RAM
+            cpu.mmu.write_byte(0xff3e, 0x5a);
+            cpu.mmu.write_byte(0xff3f, 0xa5);
+            assert_eq!(0x5a, cpu.mmu.read_byte(0xff3e));
+            assert_eq!(0xa5, cpu.mmu.read_byte(0xff3f));
+            assert_eq!(0x55, cpu.mmu.read_byte(0xff35));
+        }
+
+        it "再生中は読み出し中のバイト以外にアクセスできない" {
+            let mut cpu = cpu();
+            cpu.mmu.write_byte(0xff1a, 0x80);
+            cpu.mmu.write_byte(0xff1d, 0x00);
+            cpu.mmu.write_byte(0xff1e, 0x80);
+            cpu.mmu.run(3);
+            assert_eq!(0xff, cpu.mmu.read_byte(0xff30));
+            cpu.mmu.write_byte(0xff31, 0x12);
+
+            cpu.mmu.write_byte(0xff1a, 0x00);
+            assert_eq!(0x00, cpu.mmu.read_byte(0xff31));
+        }
+
+        fn peak_to_peak(level: u8) -> i32 {
+            let mut cpu = cpu();
+            for i in 0..16 {
+                cpu.mmu.write_byte(0xff30 + i, if i < 8 { 0xff } else { 0x00 });
+            }
+            cpu.mmu.write_byte(0xff24, 0x77);
+            cpu.mmu.write_byte(0xff25, 0x44);
+            cpu.mmu.write_byte(0xff1a, 0x80);
+            cpu.mmu.write_byte(0xff1c, level);
+            cpu.mmu.write_byte(0xff1d, 0x00);
+            cpu.mmu.write_byte(0xff1e, 0x86); // 64 Hz
+            assert_eq!(0x9f | level, cpu.mmu.read_byte(0xff1c));
+            assert_eq!(0x04, cpu.mmu.read_byte(0xff26) & 0x04);
+
+            cpu.mmu.run(SECOND / 10);
+            let mut buffer = vec![0; 8820];
+            let n = cpu.mmu.apu.drain_samples(&mut buffer);
+            // skip the pop while the high-pass filter settles
+            let samples = buffer[n / 2..n].iter().map(|s| *s as i32);
+            samples.clone().max().unwrap() - samples.min().unwrap()
+        }
+
+        it "出力レベルで音量が変わる" {
+            let levels: Vec<i32> = [0x00, 0x60, 0x40, 0x20].iter().map(|l| peak_to_peak(*l)).collect();
+            assert!(levels[0] < 16);
+            assert!(levels[1] > 0);
+            assert!(levels[2] > levels[1] * 3 / 2);
+            assert!(levels[3] > levels[2] * 3 / 2);
+        }
+    }
+}
@@ -0,0 +1,321 @@
+#[cfg(test)]
+extern crate rstest;
+extern crate speculate;
+
+use rstest::*;
+use speculate::speculate;
+
+use gameboy_rs_lib::{CartridgeError, CartridgeHeader, CPU};
+
+// fills every ROM bank with its own bank number
+fn rom(cartridge_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+    let banks = 2 << rom_size;
+    let mut rom = Vec::with_capacity(banks * 0x4000);
+    for bank in 0..banks {
+        rom.resize((bank + 1) * 0x4000, bank as u8);
+    }
+    rom[0x0147] = cartridge_type;
+    rom[0x0148] = rom_size;
+    rom[0x0149] = ram_size;
+    rom
+}
+
+speculate! {
+    describe "ヘッダ" {
+        fn header_rom() -> Vec<u8> {
+            let mut rom = rom(0x13, 0x01, 0x03);
+            rom[0x0134..0x013b].copy_from_slice(b"POKEMON");
+            rom[0x013f..0x0143].copy_from_slice(b"AAUJ");
+            rom[0x0143] = 0x80;
+            rom[0x0144..0x0146].copy_from_slice(b"01");
+            rom[0x014b] = 0x33;
+            rom[0x014c] = 0x01;
+            rom[0x014d] = rom[0x0134..=0x014c]
+                .iter()
+                .fold(0u8, |x, &v| x.wrapping_sub(v).wrapping_sub(1));
+            let sum = rom.iter().fold(0u16, |x, &v| x.wrapping_add(v as u16));
+            rom[0x014e] = (sum >> 8) as u8;
+            rom[0x014f] = sum as u8;
+            rom
+        }
+
+        it "ヘッダの各フィールドを読み取れる" {
+            let header = CartridgeHeader::new(&header_rom()).unwrap();
+            assert_eq!("POKEMON", header.title);
+            assert_eq!("AAUJ", header.manufacturer_code);
+            assert_eq!(0x80, header.cgb_flag);
+            assert_eq!("01", header.licensee());
+            assert_eq!(0x13, header.cartridge_type);
+            assert_eq!(64 * 1024, header.rom_size);
+            assert_eq!(32 * 1024, header.ram_size);
+            assert_eq!(0x01, header.version);
+            assert_eq!(Ok(()), header.verify());
+        }
+
+        it "CGBフラグでなければ0x0143までタイトルとして読む" {
+            let mut rom = header_rom();
+            rom[0x0143] = 0x00;
+            let header = CartridgeHeader::new(&rom).unwrap();
+            assert!(!header.is_cgb());
+            assert_eq!("POKEMON", header.title);
+            assert_eq!("", header.manufacturer_code);
+
+            rom[0x0134..0x0143].copy_from_slice(b"ABCDEFGHIJKLMNO");
+            rom[0x0143] = 0x84;
+            let header = CartridgeHeader::new(&rom).unwrap();
+            assert_eq!("ABCDEFGHIJKLMNO\u{84}", header.title);
+        }
+
+        it "チェックサムの不一致を検出できる" {
+            let mut rom = header_rom();
+            rom[0x0134] = b'Q';
+            let header = CartridgeHeader::new(&rom).unwrap();
+            assert!(matches!(header.verify(), Err(CartridgeError::HeaderChecksum { .. })));
+        }
+
+        #[rstest(offset, v, expected,
+            case(0x0147, 0xfd, CartridgeError::UnsupportedType(0xfd)),
+            case(0x0148, 0x10, CartridgeError::InvalidRomSize(0x10)),
+            case(0x0149, 0x07, CartridgeError::InvalidRamSize(0x07)),
+            case(0x0148, 0x02, CartridgeError::RomSizeMismatch { expected: 128 * 1024, actual: 64 * 1024 }),
+        )]
+        fn 不正なROMはエラーになる(offset: usize, v: u8, expected: CartridgeError) {
+            let mut rom = header_rom();
+            rom[offset] = v;
+            assert_eq!(Some(expected), CPU::new(rom).err());
+        }
+
+        it "ヘッダに満たないROMはエラーになる" {
+            assert_eq!(Some(CartridgeError::TooSmall(0x100)), CPU::new(vec![0; 0x100]).err());
+        }
+    }
+
+    describe "カートリッジ種別" {
+        #[rstest(cartridge_type, expected,
+            case(0x00, 0x01),
+            case(0x01, 0x03),
+            case(0x03, 0x03),
+            case(0x13, 0x03),
+            case(0x05, 0x01),
+        )]
+        fn 種別に応じてバンク切り替えが行われる(cartridge_type: u8, expected: u8) {
+            let mut cpu = CPU::new(rom(cartridge_type, 0x02, 0x02)).unwrap();
+            cpu.mmu.write_byte(0x2000, 0x03);
+            assert_eq!(expected, cpu.mmu.read_byte(0x4000));
+        }
+
+        #[rstest(cartridge_type, expected,
+            case(0x08, 0x5a),
+            case(0x03, 0x5a),
+        )]
+        fn 外部RAMを読み書きできる(cartridge_type: u8, expected: u8) {
+            let mut cpu = CPU::new(rom(cartridge_type, 0x00, 0x02)).unwrap();
+            cpu.mmu.write_byte(0x0000, 0x0a);
+            cpu.mmu.write_byte(0xa000, 0x5a);
+            assert_eq!(expected, cpu.mmu.read_byte(0xa000));
+        }
+    }
+
+    describe "MBC2" {
+        it "アドレスのbit8でレジスタを選択する" {
+            let mut cpu = CPU::new(rom(0x05, 0x02, 0x00)).unwrap();
+            cpu.mmu.write_byte(0x2100, 0x03);
+            assert_eq!(0x03, cpu.mmu.read_byte(0x4000));
+        }
+
+        it "内蔵RAMは4bit幅でミラーされる" {
+            let mut cpu = CPU::new(rom(0x06, 0x00, 0x00)).unwrap();
+            cpu.mmu.write_byte(0x0000, 0x0a);
+            cpu.mmu.write_byte(0xa000, 0x5a);
+            assert_eq!(0xfa, cpu.mmu.read_byte(0xa000));
+            assert_eq!(0xfa, cpu.mmu.read_byte(0xa200));
+            assert_eq!(0xfa, cpu.mmu.read_byte(0xbe00));
+        }
+    }
+
+    describe "MBC5" {
+        #[rstest(lo, hi, expected,
+            case(0x00, 0x00, 0x00),
+            case(0x05, 0x00, 0x05),
+            case(0x05, 0x01, 0xaa),
+        )]
+        fn ROMバンク番号を9bitで切り替えられる(lo: u8, hi: u8, expected: u8) {
+            let mut rom = rom(0x19, 0x08, 0x00);
+            rom[0x105 * 0x4000] = 0xaa;
+            let mut cpu = CPU::new(rom).unwrap();
+            cpu.mmu.write_byte(0x2000, lo);
+            cpu.mmu.write_byte(0x3000, hi);
+            assert_eq!(expected, cpu.mmu.read_byte(0x4000));
+        }
+
+        #[rstest(rom_size, banks, bank,
+            case(0x07, 256, 0x6a),
+            case(0x54, 96, 0x0a),
+        )]
+        fn 拡張ROMサイズを読み込める(rom_size: u8, banks: usize, bank: usize) {
+            let mut rom = rom(0x19, 0x00, 0x00);
+            rom[0x0148] = rom_size;
+            rom.resize(banks * 0x4000, 0x00);
+            rom[bank * 0x4000] = 0xaa;
+            let mut cpu = CPU::new(rom).unwrap();
+            cpu.mmu.write_byte(0x2000, 0x6a);
+            assert_eq!(0xaa, cpu.mmu.read_byte(0x4000));
+        }
+
+        it "16バンクのRAMを切り替えられる" {
+            let mut cpu = CPU::new(rom(0x1b, 0x00, 0x04)).unwrap();
+            cpu.mmu.write_byte(0x0000, 0x0a);
+            for bank in 0..16 {
+                cpu.mmu.write_byte(0x4000, bank);
+                cpu.mmu.write_byte(0xa000, bank);
+            }
+            cpu.mmu.write_byte(0x4000, 0x0f);
+            assert_eq!(0x0f, cpu.mmu.read_byte(0xa000));
+            cpu.mmu.write_byte(0x4000, 0x03);
+            assert_eq!(0x03, cpu.mmu.read_byte(0xa000));
+        }
+    }
+
+    describe "MBC3 RTC" {
+        const SECOND: u32 = 4 * 1024 * 1024;
+
+        fn latch(cpu: &mut CPU) {
+            cpu.mmu.write_byte(0x6000, 0x00);
+            cpu.mmu.write_byte(0x6000, 0x01);
+        }
+
+        it "ラッチした時点のエミュレーション時間を読み取れる" {
+            let mut cpu = CPU::new(rom(0x10, 0x00, 0x02)).unwrap();
+            cpu.mmu.write_byte(0x0000, 0x0a);
+            cpu.mmu.write_byte(0x4000, 0x08);
+
+            cpu.mmu.run(SECOND * 61);
+            latch(&mut cpu);
+            cpu.mmu.run(SECOND);
+            assert_eq!(1, cpu.mmu.read_byte(0xa000));
+
+            cpu.mmu.write_byte(0x4000, 0x09);
+            assert_eq!(1, cpu.mmu.read_byte(0xa000));
+        }
+
+        it "日カウンタが溢れるとキャリーフラグが立つ" {
+            let mut cpu = CPU::new(rom(0x0f, 0x00, 0x00)).unwrap();
+            cpu.mmu.write_byte(0x0000, 0x0a);
+            let registers = [(0x08, 59), (0x09, 59), (0x0a, 23), (0x0b, 0xff), (0x0c, 0x01)];
+            for (select, v) in registers.iter() {
+                cpu.mmu.write_byte(0x4000, *select);
+                cpu.mmu.write_byte(0xa000, *v);
+            }
+
+            cpu.mmu.run(SECOND);
+            latch(&mut cpu);
+            cpu.mmu.write_byte(0x4000, 0x0b);
+            assert_eq!(0x00, cpu.mmu.read_byte(0xa000));
+            cpu.mmu.write_byte(0x4000, 0x0c);
+            assert_eq!(0x80, cpu.mmu.read_byte(0xa000));
+        }
+
+        it "haltフラグが立っている間は時間が進まない" {
+            let mut cpu = CPU::new(rom(0x0f, 0x00, 0x00)).unwrap();
+            cpu.mmu.write_byte(0x0000, 0x0a);
+            cpu.mmu.write_byte(0x4000, 0x0c);
+            cpu.mmu.write_byte(0xa000, 0x40);
+
+            cpu.mmu.run(SECOND * 2);
+            latch(&mut cpu);
+            cpu.mmu.write_byte(0x4000, 0x08);
+            assert_eq!(0, cpu.mmu.read_byte(0xa000));
+        }
+    }
+
+    describe "バッテリーバックアップ" {
+        it "RAMを保存して復元できる" {
+            let mut cpu = CPU::new(rom(0x03, 0x00, 0x02)).unwrap();
+            assert!(cpu.mmu.cartridge.has_battery());
+            cpu.mmu.write_byte(0x0000, 0x0a);
+            cpu.mmu.write_byte(0xa123, 0x5a);
+            let data = cpu.mmu.cartridge.save_ram();
+            assert_eq!(8 * 1024, data.len());
+
+            let mut cpu = CPU::new(rom(0x03, 0x00, 0x02)).unwrap();
+            cpu.mmu.cartridge.load_ram(&data);
+            cpu.mmu.write_byte(0x0000, 0x0a);
+            assert_eq!(0x5a, cpu.mmu.read_byte(0xa123));
+        }
+
+        it "RTCは48byteのフッタとして保存される" {
+            let mut cpu = CPU::new(rom(0x10, 0x00, 0x02)).unwrap();
+            cpu.mmu.write_byte(0x0000, 0x0a);
+            cpu.mmu.write_byte(0x4000, 0x09);
+            cpu.mmu.write_byte(0xa000, 42);
+            let data = cpu.mmu.cartridge.save_ram();
+            assert_eq!(8 * 1024 + 48, data.len());
+            assert_eq!(42, data[8 * 1024 + 4]);
+
+            let mut cpu = CPU::new(rom(0x10, 0x00, 0x02)).unwrap();
+            cpu.mmu.cartridge.load_ram(&data);
+            cpu.mmu.write_byte(0x0000, 0x0a);
+            cpu.mmu.write_byte(0x6000, 0x00);
+            cpu.mmu.write_byte(0x6000, 0x01);
+            cpu.mmu.write_byte(0x4000, 0x09);
+            assert_eq!(42, cpu.mmu.read_byte(0xa000));
+        }
+
+        #[rstest(control, elapsed, expected,
+            case(0x00, 3661, [1, 1, 1, 0]),
+            case(0x00, 86400 * 2 + 30, [30, 0, 0, 2]),
+            case(0x40, 3661, [0, 0, 0, 0]),
+        )]
+        fn 保存してからの経過時間がRTCに加わる(control: u8, elapsed: u64, expected: [u8; 4]) {
+            let mut data = vec![0; 8 * 1024 + 48];
+            data[8 * 1024 + 16] = control;
+            data[8 * 1024 + 40..].copy_from_slice(&1_600_000_000u64.to_le_bytes());
+
+            let mut cpu = CPU::new(rom(0x10, 0x00, 0x02)).unwrap();
+            assert_eq!(Some(1_600_000_000), cpu.mmu.cartridge.load_ram(&data));
+            cpu.mmu.cartridge.advance_rtc(elapsed);
+            cpu.mmu.write_byte(0x0000, 0x0a);
+            cpu.mmu.write_byte(0x6000, 0x00);
+            cpu.mmu.write_byte(0x6000, 0x01);
+            let mut registers = [0; 4];
+            for (i, select) in (0x08..=0x0b).enumerate() {
+                cpu.mmu.write_byte(0x4000, select);
+                registers[i] = cpu.mmu.read_byte(0xa000);
+            }
+            assert_eq!(expected, registers);
+        }
+
+        it "バッテリーのないカートリッジは保存対象外" {
+            let cpu = CPU::new(rom(0x01, 0x00, 0x00)).unwrap();
+            assert!(!cpu.mmu.cartridge.has_battery());
+        }
+
+        #[rstest(cartridge_type, battery,
+            case(0x06, true),
+            case(0x10, true),
+            case(0x1b, true),
+            case(0x11, false),
+            case(0x19, false),
+        )]
+        fn カートリッジタイプでバッテリーの有無が決まる(cartridge_type: u8, battery: bool) {
+            let cpu = CPU::new(rom(cartridge_type, 0x00, 0x02)).unwrap();
+            assert_eq!(battery, cpu.mmu.cartridge.has_battery());
+        }
+    }
+
+    describe "ブートROM" {
+        it "0xff50への書き込みでブートROMが外れる" {
+            let mut cpu = CPU::new(rom(0x00, 0x00, 0x00)).unwrap();
+            cpu.mmu.cartridge.load_bios(vec![0x31; 0x100]);
+            assert_eq!(0x31, cpu.mmu.read_byte(0x0000));
+            assert_eq!(0xfe, cpu.mmu.read_byte(0xff50));
+
+            cpu.mmu.write_byte(0xff50, 0x01);
+            assert_eq!(0x00, cpu.mmu.read_byte(0x0000));
+            assert_eq!(0xff, cpu.mmu.read_byte(0xff50));
+
+            cpu.mmu.write_byte(0xff50, 0x00);
+            assert_eq!(0x00, cpu.mmu.read_byte(0x0000));
+        }
+    }
+}
@@ -0,0 +1,108 @@
+#[cfg(test)]
+extern crate rstest;
+extern crate speculate;
+
+use rstest::*;
+use speculate::speculate;
+
+use gameboy_rs_lib::{EmuError, CPU};
+
+fn rom(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+    rom
+}
+
+fn cgb_rom(program: &[u8]) -> Vec<u8> {
+    let mut rom = rom(program);
+    rom[0x0143] = 0x80;
+    rom
+}
+
+// ld a, 0x01; ldh (0x4d), a; stop
+const SPEED_SWITCH: [u8; 6] = [0x3e, 0x01, 0xe0, 0x4d, 0x10, 0x00];
+
+speculate! {
+    describe "不正な命令" {
+        #[rstest(opcode,
+            case(0xd3),
+            case(0xe4),
+            case(0xfd),
+        )]
+        fn 不正な命令でCPUがロックする(opcode: u8) {
+            let mut cpu = CPU::new(rom(&[0x00, opcode, 0x3c])).unwrap();
+            cpu.init();
+            assert_eq!(Ok(4), cpu.run());
+            assert_eq!(Err(EmuError::IllegalOpcode { opcode, addr: 0x0101 }), cpu.run());
+            for _ in 0..10 {
+                assert_eq!(Ok(4), cpu.run());
+            }
+        }
+    }
+
+    describe "未使用のI/Oレジスタ" {
+        #[rstest(addr,
+            case(0xff03),
+            case(0xff08),
+            case(0xff4c),
+            case(0xff7e),
+        )]
+        fn オープンバスとして0xffを返す(addr: u16) {
+            let mut cpu = CPU::new(rom(&[])).unwrap();
+            cpu.mmu.write_byte(addr, 0x00);
+            assert_eq!(0xff, cpu.mmu.read_byte(addr));
+        }
+    }
+
+    describe "アドレスの折り返し" {
+        it "0xffffからのワード読み出しは0x0000に続く" {
+            let mut rom = rom(&[]);
+            rom[0x0000] = 0x34;
+            let mut cpu = CPU::new(rom).unwrap();
+            cpu.init();
+            cpu.mmu.write_byte(0xffff, 0x12);
+            assert_eq!(0x3412, cpu.mmu.read_word(0xffff));
+        }
+    }
+
+    describe "倍速モード" {
+        it "KEY1で準備してSTOPで切り替える" {
+            let mut cpu = CPU::new(cgb_rom(&SPEED_SWITCH)).unwrap();
+            cpu.init();
+            assert_eq!(0x7e, cpu.mmu.read_byte(0xff4d));
+            cpu.run().unwrap();
+            cpu.run().unwrap();
+            assert_eq!(0x7f, cpu.mmu.read_byte(0xff4d));
+            cpu.run().unwrap();
+            assert_eq!(0xfe, cpu.mmu.read_byte(0xff4d));
+            assert!(cpu.mmu.is_double_speed());
+            // nop takes 4 CPU ticks, which is 2 ticks of the base clock
+            assert_eq!(Ok(2), cpu.run());
+        }
+
+        it "タイマーは2倍の速さで進む" {
+            let mut cpu = CPU::new(cgb_rom(&SPEED_SWITCH)).unwrap();
+            cpu.init();
+            for _ in 0..3 {
+                cpu.run().unwrap();
+            }
+            let div = cpu.mmu.read_byte(0xff04);
+            let mut ticks = 0;
+            while ticks < 512 {
+                ticks += cpu.run().unwrap();
+            }
+            assert_eq!(div + 4, cpu.mmu.read_byte(0xff04));
+        }
+
+        it "DMGではSTOPで切り替わらない" {
+            let mut cpu = CPU::new(rom(&SPEED_SWITCH)).unwrap();
+            cpu.init();
+            for _ in 0..3 {
+                cpu.run().unwrap();
+            }
+            assert_eq!(0xff, cpu.mmu.read_byte(0xff4d));
+            assert!(!cpu.mmu.is_double_speed());
+            assert_eq!(Ok(4), cpu.run());
+        }
+    }
+}
@@ -0,0 +1,145 @@
+#[cfg(test)]
+extern crate rstest;
+extern crate speculate;
+
+use rstest::*;
+use speculate::speculate;
+
+use gameboy_rs_lib::CPU;
+
+fn cgb_rom(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+    rom[0x0143] = 0x80;
+    rom
+}
+
+// 0xc000-0xc03f filled with 1, 2, 3, ... and 0x8000 as destination
+fn cpu(program: &[u8]) -> CPU {
+    let mut cpu = CPU::new(cgb_rom(program)).unwrap();
+    cpu.init();
+    for i in 0..0x40 {
+        cpu.mmu.write_byte(0xc000 + i, i as u8 + 1);
+    }
+    cpu.mmu.write_byte(0xff51, 0xc0);
+    cpu.mmu.write_byte(0xff52, 0x00);
+    cpu.mmu.write_byte(0xff53, 0x00);
+    cpu.mmu.write_byte(0xff54, 0x00);
+    cpu
+}
+
+fn copied(cpu: &CPU) -> usize {
+    (0..0x40)
+        .take_while(|&i| cpu.mmu.read_byte(0x8000 + i) == i as u8 + 1)
+        .count()
+}
+
+// one scanline from the start of mode 2
+const LINE: u32 = 456;
+
+// steps like the CPU; one call spanning several HBlanks would copy a single HBlank DMA block
+fn run(cpu: &mut CPU, ticks: u32) {
+    for _ in 0..ticks / 4 {
+        cpu.mmu.run(4);
+    }
+}
+
+speculate! {
+    describe "汎用DMA" {
+        it "すぐにコピーしてCPUを停止させる" {
+            // ld a, 0x01; ldh (0x55), a
+            let mut cpu = cpu(&[0x3e, 0x01, 0xe0, 0x55]);
+            cpu.mmu.write_byte(0xff40, 0x00);
+            cpu.run().unwrap();
+            assert_eq!(Ok(12 + 2 * 32), cpu.run());
+            assert_eq!(0x20, copied(&cpu));
+            assert_eq!(0xff, cpu.mmu.read_byte(0xff55));
+        }
+    }
+
+    describe "HBlank DMA" {
+        it "HBlankごとに16バイトずつコピーする" {
+            let mut cpu = cpu(&[]);
+            cpu.mmu.write_byte(0xff55, 0x81);
+            assert_eq!(0x01, cpu.mmu.read_byte(0xff55));
+            assert_eq!(0, copied(&cpu));
+
+            run(&mut cpu, LINE);
+            assert_eq!(0x10, copied(&cpu));
+            assert_eq!(0x00, cpu.mmu.read_byte(0xff55));
+
+            run(&mut cpu, LINE);
+            assert_eq!(0x20, copied(&cpu));
+            assert_eq!(0xff, cpu.mmu.read_byte(0xff55));
+        }
+
+        it "途中で止められる" {
+            let mut cpu = cpu(&[]);
+            cpu.mmu.write_byte(0xff55, 0x83);
+            run(&mut cpu, LINE);
+            cpu.mmu.write_byte(0xff55, 0x00);
+            assert_eq!(0x82, cpu.mmu.read_byte(0xff55));
+
+            run(&mut cpu, LINE * 2);
+            assert_eq!(0x10, copied(&cpu));
+        }
+
+        #[rstest(addr,
+            case(0xff51),
+            case(0xff55),
+        )]
+        fn DMGでは使えない(addr: u16) {
+            let mut cpu = CPU::new(vec![0; 0x8000]).unwrap();
+            cpu.mmu.write_byte(addr, 0x00);
+            assert_eq!(0xff, cpu.mmu.read_byte(addr));
+        }
+    }
+
+    describe "OAM DMA" {
+        before {
+            let mut cpu = CPU::new(vec![0; 0x8000]).unwrap();
+            cpu.init();
+            for i in 0..0xa0 {
+                cpu.mmu.write_byte(0xc000 + i, i as u8 + 1);
+            }
+            cpu.mmu.write_byte(0xff46, 0xc0);
+            // the rest of the writing instruction
+            cpu.mmu.run(4);
+        }
+
+        it "160 Mサイクルかけて転送する" {
+            cpu.mmu.run(80 * 4);
+            assert_eq!(80, cpu.mmu.ppu.oam.iter().take_while(|&&b| b != 0).count());
+            cpu.mmu.run(80 * 4);
+            let expected: Vec<u8> = (1..=0xa0).collect();
+            assert_eq!(expected, cpu.mmu.ppu.oam.to_vec());
+            assert_eq!(0xc0, cpu.mmu.read_byte(0xff46));
+        }
+
+        it "転送中はHRAMだけにアクセスできる" {
+            cpu.mmu.run(10 * 4);
+            // DMA bus value
+            assert_eq!(10, cpu.mmu.read_byte(0xc000));
+            assert_eq!(10, cpu.mmu.read_byte(0x0100));
+            cpu.mmu.write_byte(0xc100, 0x12);
+            cpu.mmu.write_byte(0xff80, 0x34);
+            assert_eq!(0x34, cpu.mmu.read_byte(0xff80));
+
+            cpu.mmu.run(150 * 4);
+            assert_eq!(0x00, cpu.mmu.read_byte(0xc100));
+            assert_eq!(0x01, cpu.mmu.read_byte(0xc000));
+        }
+
+        it "0xfe以上のページはWRAMから転送する" {
+            cpu.mmu.run(160 * 4);
+            for i in 0..0xa0 {
+                cpu.mmu.write_byte(0xde00 + i, 0xa0 - i as u8);
+            }
+            cpu.mmu.write_byte(0xff46, 0xfe);
+            cpu.mmu.run(4);
+            cpu.mmu.run(160 * 4);
+            let expected: Vec<u8> = (1..=0xa0).rev().collect();
+            assert_eq!(expected, cpu.mmu.ppu.oam.to_vec());
+        }
+    }
+}
@@ -0,0 +1,98 @@
+#[cfg(test)]
+extern crate rstest;
+extern crate speculate;
+
+use rstest::*;
+use speculate::speculate;
+
+use gameboy_rs_lib::{LinkCable, CPU};
+
+// writes `data` to SB and `control` to SC, waits for the transfer and stores SB to 0xff80
+fn transfer(data: u8, control: u8) -> Vec<u8> {
+    vec![
+        0x3e, data, // ld a, data
+        0xe0, 0x01, // ldh (0x01), a
+        0x3e, control, // ld a, control
+        0xe0, 0x02, // ldh (0x02), a
+        0xf0, 0x02, // ldh a, (0x02)
+        0xcb, 0x7f, // bit 7, a
+        0x20, 0xfa, // jr nz, -6
+        0xf0, 0x01, // ldh a, (0x01)
+        0xe0, 0x80, // ldh (0x80), a
+        0x18, 0xfe, // jr -2
+    ]
+}
+
+fn cpu(program: &[u8]) -> CPU {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+    let mut cpu = CPU::new(rom).unwrap();
+    cpu.init();
+    cpu.mmu.write_byte(0xff80, 0x00);
+    cpu
+}
+
+speculate! {
+    describe "通信ケーブル" {
+        #[rstest(left, right,
+            case(0x81, 0x80),
+            case(0x80, 0x81),
+        )]
+        fn 内部クロック側が相手とバイトを交換する(left: u8, right: u8) {
+            let mut link = LinkCable::new(cpu(&transfer(0x42, left)), cpu(&transfer(0x24, right)));
+            link.run_ticks(8192).unwrap();
+            assert_eq!(0x24, link.left.mmu.read_byte(0xff80));
+            assert_eq!(0x42, link.right.mmu.read_byte(0xff80));
+        }
+
+        it "相手が待っていなければ0xffを受け取る" {
+            // ld a, 0x24; ldh (0x01), a; jr -2
+            let mut link = LinkCable::new(
+                cpu(&transfer(0x42, 0x81)),
+                cpu(&[0x3e, 0x24, 0xe0, 0x01, 0x18, 0xfe]),
+            );
+            link.run_ticks(8192).unwrap();
+            assert_eq!(0xff, link.left.mmu.read_byte(0xff80));
+            assert_eq!(0x24, link.right.mmu.read_byte(0xff01));
+        }
+
+        it "両方が外部クロックなら転送しない" {
+            let mut link = LinkCable::new(cpu(&transfer(0x42, 0x80)), cpu(&transfer(0x24, 0x80)));
+            link.run_ticks(70224).unwrap();
+            assert_eq!(0x00, link.left.mmu.read_byte(0xff80));
+            assert_eq!(0x00, link.right.mmu.read_byte(0xff80));
+        }
+
+        #[rstest(control,
+            case(0x00),
+            case(0x01),
+        )]
+        fn 外部クロックの転送を取り消すと交換しない(control: u8) {
+            let cancel = [
+                0x3e, 0x24, // ld a, 0x24
+                0xe0, 0x01, // ldh (0x01), a
+                0x3e, 0x80, // ld a, 0x80
+                0xe0, 0x02, // ldh (0x02), a
+                0x3e, control, // ld a, control
+                0xe0, 0x02, // ldh (0x02), a
+                0x18, 0xfe, // jr -2
+            ];
+            let mut link = LinkCable::new(cpu(&transfer(0x42, 0x81)), cpu(&cancel));
+            link.run_ticks(8192).unwrap();
+            assert_eq!(0xff, link.left.mmu.read_byte(0xff80));
+            assert_eq!(0x24, link.right.mmu.read_byte(0xff01));
+        }
+
+        it "2台を同じ速さで進める" {
+            let mut link = LinkCable::new(cpu(&[0x18, 0xfe]), cpu(&[0x00, 0x18, 0xfd]));
+            let mut left = 0;
+            let mut right = 0;
+            for _ in 0..1000 {
+                let (l, r) = link.run().unwrap();
+                left += l as i64;
+                right += r as i64;
+                assert!((left - right).abs() <= 12);
+            }
+        }
+    }
+}
@@ -0,0 +1,695 @@
+#[cfg(test)]
+extern crate rstest;
+extern crate speculate;
+
+use rstest::*;
+use speculate::speculate;
+
+use gameboy_rs_lib::{Palette, Renderer, CPU};
+
+const RED: u32 = 0xff0000;
+const BLUE: u32 = 0x0000ff;
+const WHITE: u32 = 0xffffff;
+
+fn cpu(cgb_flag: u8) -> CPU {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0143] = cgb_flag;
+    let mut cpu = CPU::new(rom).unwrap();
+    cpu.init();
+    // LCD off while VRAM and OAM are set up
+    cpu.mmu.write_byte(0xff40, 0x00);
+    cpu
+}
+
+// fills a tile so that every pixel has color number 3 (or 1 when `hi` is false)
+fn write_solid_tile(cpu: &mut CPU, addr: u16, hi: bool) {
+    for i in 0..8 {
+        cpu.mmu.write_byte(addr + i * 2, 0xff);
+        cpu.mmu
+            .write_byte(addr + i * 2 + 1, if hi { 0xff } else { 0x00 });
+    }
+}
+
+// RGB555 colors of one palette through BCPS/BCPD or OCPS/OCPD
+fn write_palette(cpu: &mut CPU, spec: u16, palette: u8, colors: [u16; 4]) {
+    cpu.mmu.write_byte(spec, 0x80 | (palette * 8));
+    for c in colors.iter() {
+        cpu.mmu.write_byte(spec + 1, *c as u8);
+        cpu.mmu.write_byte(spec + 1, (*c >> 8) as u8);
+    }
+}
+
+// renders line 0 with the given LCDC
+fn render_line(cpu: &mut CPU, lcdc: u8) -> Vec<u32> {
+    cpu.mmu.write_byte(0xff40, lcdc);
+    cpu.mmu.run(80);
+    cpu.mmu.ppu.frame_buffer[0..160].to_vec()
+}
+
+// CGB cart with tile 1 at BG (0, 0) and a few palettes
+fn scene() -> CPU {
+    let mut cpu = cpu(0x80);
+    // tile 1 in bank 0: color 3, tile 1 in bank 1: color 1 on the left half
+    write_solid_tile(&mut cpu, 0x8010, true);
+    cpu.mmu.write_byte(0xff4f, 0x01);
+    for i in 0..8 {
+        cpu.mmu.write_byte(0x8010 + i * 2, 0xf0);
+    }
+    cpu.mmu.write_byte(0xff4f, 0x00);
+    write_palette(&mut cpu, 0xff68, 0, [0x7fff, 0x7fff, 0x7fff, 0x7c00]);
+    write_palette(&mut cpu, 0xff68, 2, [0x7fff, 0x001f, 0x001f, 0x001f]);
+    write_palette(&mut cpu, 0xff6a, 0, [0x0000, 0x001f, 0x001f, 0x001f]);
+    write_palette(&mut cpu, 0xff6a, 1, [0x0000, 0x7c00, 0x7c00, 0x7c00]);
+    // BG tile 0 at (0, 0) uses tile 1
+    cpu.mmu.write_byte(0x9800, 0x01);
+    cpu
+}
+
+// DMG scene with two patterned tiles, scrolling, the window and overlapping sprites
+fn dmg_scene() -> CPU {
+    let mut cpu = cpu(0x00);
+    for i in 0..8 {
+        cpu.mmu.write_byte(0x8010 + i * 2, 0x0f);
+        cpu.mmu.write_byte(0x8011 + i * 2, 0x33);
+        cpu.mmu.write_byte(0x8020 + i * 2, 0xaa << (i & 1));
+        cpu.mmu.write_byte(0x8021 + i * 2, 0xcc);
+    }
+    for i in 0..0x400 {
+        cpu.mmu.write_byte(0x9800 + i, (i % 3) as u8);
+        cpu.mmu.write_byte(0x9c00 + i, 2 - (i % 3) as u8);
+    }
+    cpu.mmu.write_byte(0xff42, 5);
+    cpu.mmu.write_byte(0xff43, 3);
+    cpu.mmu.write_byte(0xff47, 0xe4);
+    cpu.mmu.write_byte(0xff48, 0xd2);
+    cpu.mmu.write_byte(0xff49, 0x1b);
+    cpu.mmu.write_byte(0xff4a, 40);
+    cpu.mmu.write_byte(0xff4b, 87);
+    // y, x, tile, flags
+    let sprites = [
+        (20, 4, 1, 0x00),
+        (20, 8, 2, 0x10),
+        (24, 12, 1, 0x20),
+        (60, 100, 2, 0x80),
+        (64, 104, 1, 0x40),
+        (100, 165, 2, 0x00),
+    ];
+    for (i, (y, x, tile, flags)) in sprites.iter().enumerate() {
+        let addr = 0xfe00 + i as u16 * 4;
+        cpu.mmu.write_byte(addr, *y);
+        cpu.mmu.write_byte(addr + 1, *x);
+        cpu.mmu.write_byte(addr + 2, *tile);
+        cpu.mmu.write_byte(addr + 3, *flags);
+    }
+    cpu
+}
+
+fn render_frame(cpu: &mut CPU, renderer: Renderer, lcdc: u8) -> Vec<u32> {
+    cpu.mmu.ppu.set_renderer(renderer);
+    cpu.mmu.write_byte(0xff40, lcdc);
+    for _ in 0..70224 / 4 {
+        cpu.mmu.run(4);
+    }
+    cpu.mmu.ppu.frame_buffer.to_vec()
+}
+
+// length of mode 3 on line 0 with the FIFO renderer
+fn mode3_clocks(cpu: &mut CPU, lcdc: u8) -> u32 {
+    cpu.mmu.ppu.set_renderer(Renderer::Fifo);
+    cpu.mmu.write_byte(0xff40, lcdc);
+    let mut clocks = 0;
+    while !cpu.mmu.ppu.hblank {
+        cpu.mmu.ppu.run(1);
+        clocks += 1;
+    }
+    clocks - 80
+}
+
+fn stat_mode(cpu: &CPU) -> u8 {
+    cpu.mmu.read_byte(0xff41) & 0x03
+}
+
+fn stat_interrupt(cpu: &CPU) -> bool {
+    cpu.mmu.read_byte(0xff0f) & 0x02 != 0
+}
+
+// steps 4 ticks at a time until the condition holds
+fn run_until(cpu: &mut CPU, f: impl Fn(&CPU) -> bool) {
+    for _ in 0..70224 / 4 {
+        if f(cpu) {
+            return;
+        }
+        cpu.mmu.run(4);
+    }
+    panic!("timed out");
+}
+
+// DMG scene with a black BG and a window of diagonal lines, alternating direction every tile row
+fn window_scene(wy: u8, wx: u8) -> CPU {
+    let mut cpu = cpu(0x00);
+    for i in 0..8 {
+        cpu.mmu.write_byte(0x8010 + i * 2, 0x80 >> i);
+        cpu.mmu.write_byte(0x8020 + i * 2, 0x01 << i);
+    }
+    write_solid_tile(&mut cpu, 0x8030, true);
+    for i in 0..0x400 {
+        cpu.mmu.write_byte(0x9800 + i, 3);
+        cpu.mmu.write_byte(0x9c00 + i, 1 + (i / 32 % 2) as u8);
+    }
+    cpu.mmu.write_byte(0xff47, 0xe4);
+    cpu.mmu.write_byte(0xff4a, wy);
+    cpu.mmu.write_byte(0xff4b, wx);
+    cpu
+}
+
+// renders a frame one line at a time, calling `f` at the start of each line
+fn render_lines(cpu: &mut CPU, renderer: Renderer, f: impl Fn(&mut CPU, u8)) -> Vec<u32> {
+    cpu.mmu.ppu.set_renderer(renderer);
+    cpu.mmu.write_byte(0xff40, 0xf1);
+    for ly in 0..144 {
+        f(cpu, ly);
+        cpu.mmu.run(456);
+    }
+    cpu.mmu.ppu.frame_buffer.to_vec()
+}
+
+// shades in the reference rows of `window_scene`
+const W: u32 = WHITE;
+const G: u32 = 0xaaaaaa;
+const K: u32 = 0x000000;
+
+// expected frame of `window_scene`; `row` gives the window row drawn on each line
+fn window_frame(wx: u8, row: impl Fn(usize) -> Option<usize>) -> Vec<u32> {
+    let mut frame = vec![0x000000; 160 * 144];
+    for y in 0..144 {
+        let r = match row(y) {
+            Some(r) => r,
+            None => continue,
+        };
+        for x in (wx as usize).saturating_sub(7)..160 {
+            let c = x + 7 - wx as usize;
+            let dot = if r / 8 % 2 == 0 {
+                c % 8 == r % 8
+            } else {
+                7 - c % 8 == r % 8
+            };
+            frame[y * 160 + x] = if dot { 0xaaaaaa } else { WHITE };
+        }
+    }
+    frame
+}
+
+speculate! {
+    describe "CGBのメモリバンク" {
+        it "VBKでVRAMバンクを切り替える" {
+            let mut cpu = cpu(0x80);
+            cpu.mmu.write_byte(0x8000, 0x12);
+            cpu.mmu.write_byte(0xff4f, 0x01);
+            assert_eq!(0xff, cpu.mmu.read_byte(0xff4f));
+            assert_eq!(0x00, cpu.mmu.read_byte(0x8000));
+            cpu.mmu.write_byte(0x8000, 0x34);
+            cpu.mmu.write_byte(0xff4f, 0x00);
+            assert_eq!(0xfe, cpu.mmu.read_byte(0xff4f));
+            assert_eq!(0x12, cpu.mmu.read_byte(0x8000));
+        }
+
+        #[rstest(bank, mapped,
+            case(0, 1),
+            case(1, 1),
+            case(2, 2),
+            case(7, 7),
+        )]
+        fn SVBKでWRAMバンクを切り替える(bank: u8, mapped: u8) {
+            let mut cpu = cpu(0xc0);
+            for b in 1..8 {
+                cpu.mmu.write_byte(0xff70, b);
+                cpu.mmu.write_byte(0xd000, b);
+            }
+            cpu.mmu.write_byte(0xc000, 0xaa);
+
+            cpu.mmu.write_byte(0xff70, bank);
+            assert_eq!(0xf8 | bank, cpu.mmu.read_byte(0xff70));
+            assert_eq!(mapped, cpu.mmu.read_byte(0xd000));
+            assert_eq!(mapped, cpu.mmu.read_byte(0xf000));
+            assert_eq!(0xaa, cpu.mmu.read_byte(0xc000));
+        }
+
+        #[rstest(cgb_flag, cgb,
+            case(0x00, false),
+            case(0x80, true),
+            case(0xc0, true),
+            case(0x84, false),
+        )]
+        fn CGBフラグが0x80か0xc0ならCGBモードになる(cgb_flag: u8, cgb: bool) {
+            assert_eq!(cgb, cpu(cgb_flag).mmu.is_cgb());
+        }
+
+        it "DMGモードではCGBレジスタが無効" {
+            let mut cpu = cpu(0x00);
+            assert!(!cpu.mmu.is_cgb());
+            cpu.mmu.write_byte(0x8000, 0x12);
+            cpu.mmu.write_byte(0xff4f, 0x01);
+            cpu.mmu.write_byte(0xff70, 0x02);
+            cpu.mmu.write_byte(0xd000, 0x56);
+            cpu.mmu.write_byte(0xff70, 0x03);
+            for addr in [0xff4f, 0xff68, 0xff69, 0xff6a, 0xff6b, 0xff70].iter() {
+                assert_eq!(0xff, cpu.mmu.read_byte(*addr));
+            }
+            assert_eq!(0x12, cpu.mmu.read_byte(0x8000));
+            assert_eq!(0x56, cpu.mmu.read_byte(0xd000));
+        }
+    }
+
+    describe "CGBのパレット" {
+        #[rstest(spec,
+            case(0xff68),
+            case(0xff6a),
+        )]
+        fn 自動インクリメントで書き込める(spec: u16) {
+            let mut cpu = cpu(0x80);
+            cpu.mmu.write_byte(spec, 0x80 | 0x3e);
+            cpu.mmu.write_byte(spec + 1, 0x12);
+            cpu.mmu.write_byte(spec + 1, 0x34);
+            cpu.mmu.write_byte(spec + 1, 0x56);
+            // wraps around to index 0
+            assert_eq!(0xc1, cpu.mmu.read_byte(spec));
+
+            cpu.mmu.write_byte(spec, 0x3e);
+            assert_eq!(0x7e, cpu.mmu.read_byte(spec));
+            assert_eq!(0x12, cpu.mmu.read_byte(spec + 1));
+            cpu.mmu.write_byte(spec, 0x3f);
+            assert_eq!(0x34, cpu.mmu.read_byte(spec + 1));
+            cpu.mmu.write_byte(spec, 0x00);
+            assert_eq!(0x56, cpu.mmu.read_byte(spec + 1));
+        }
+    }
+
+    describe "CGBの描画" {
+        it "BGパレットの色で描画する" {
+            let mut cpu = scene();
+            let line = render_line(&mut cpu, 0x91);
+            assert_eq!(vec![BLUE; 8], line[0..8].to_vec());
+            assert_eq!(WHITE, line[8]);
+        }
+
+        it "属性でパレットとバンクと反転を選ぶ" {
+            let mut cpu = scene();
+            cpu.mmu.write_byte(0xff4f, 0x01);
+            // palette 2, bank 1, x flip
+            cpu.mmu.write_byte(0x9800, 0x2a);
+            cpu.mmu.write_byte(0xff4f, 0x00);
+            let line = render_line(&mut cpu, 0x91);
+            assert_eq!(vec![WHITE; 4], line[0..4].to_vec());
+            assert_eq!(vec![RED; 4], line[4..8].to_vec());
+        }
+
+        #[rstest(bg_attr, sprite_flags, lcdc, color,
+            case(0x00, 0x00, 0x93, RED),
+            case(0x80, 0x00, 0x93, BLUE),
+            case(0x00, 0x80, 0x93, BLUE),
+            // LCDC bit 0 takes the priority away from BG
+            case(0x80, 0x80, 0x92, RED),
+        )]
+        fn スプライトとBGの優先度(bg_attr: u8, sprite_flags: u8, lcdc: u8, color: u32) {
+            let mut cpu = scene();
+            cpu.mmu.write_byte(0xff4f, 0x01);
+            cpu.mmu.write_byte(0x9800, bg_attr);
+            cpu.mmu.write_byte(0xff4f, 0x00);
+            cpu.mmu.write_byte(0xfe00, 16);
+            cpu.mmu.write_byte(0xfe01, 8);
+            cpu.mmu.write_byte(0xfe02, 0x01);
+            cpu.mmu.write_byte(0xfe03, sprite_flags);
+            let line = render_line(&mut cpu, lcdc);
+            assert_eq!(color, line[0]);
+        }
+
+        it "スプライト同士はOAMの順で重なる" {
+            let mut cpu = scene();
+            cpu.mmu.write_byte(0x9800, 0x00);
+            // sprite 0 at x = 4 with palette 0, sprite 1 at x = 0 with palette 1
+            cpu.mmu.write_byte(0xfe00, 16);
+            cpu.mmu.write_byte(0xfe01, 12);
+            cpu.mmu.write_byte(0xfe02, 0x01);
+            cpu.mmu.write_byte(0xfe03, 0x00);
+            cpu.mmu.write_byte(0xfe04, 16);
+            cpu.mmu.write_byte(0xfe05, 8);
+            cpu.mmu.write_byte(0xfe06, 0x01);
+            cpu.mmu.write_byte(0xfe07, 0x01);
+            let line = render_line(&mut cpu, 0x93);
+            assert_eq!(vec![BLUE; 4], line[0..4].to_vec());
+            assert_eq!(vec![RED; 8], line[4..12].to_vec());
+        }
+    }
+
+    describe "FIFOレンダラ" {
+        #[rstest(lcdc,
+            case(0x91),
+            case(0x93),
+            case(0xb3),
+            case(0xf7),
+        )]
+        fn 静止画はスキャンラインレンダラと同じ(lcdc: u8) {
+            let mut cpu = dmg_scene();
+            let scanline = render_frame(&mut cpu, Renderer::Scanline, lcdc);
+            let mut cpu = dmg_scene();
+            let fifo = render_frame(&mut cpu, Renderer::Fifo, lcdc);
+            assert!(scanline == fifo);
+        }
+
+        #[rstest(scx, clocks,
+            case(0, 172),
+            case(3, 175),
+            case(7, 179),
+        )]
+        fn SCXの端数でモード3が伸びる(scx: u8, clocks: u32) {
+            let mut cpu = cpu(0x00);
+            cpu.mmu.write_byte(0xff43, scx);
+            assert_eq!(clocks, mode3_clocks(&mut cpu, 0x91));
+        }
+
+        it "スプライトでモード3が伸びる" {
+            let mut cpu = cpu(0x00);
+            cpu.mmu.write_byte(0xfe00, 16);
+            cpu.mmu.write_byte(0xfe01, 8);
+            assert!(mode3_clocks(&mut cpu, 0x93) > 172);
+        }
+
+        it "ウィンドウでモード3が伸びる" {
+            let mut cpu = cpu(0x00);
+            cpu.mmu.write_byte(0xff4b, 87);
+            assert!(mode3_clocks(&mut cpu, 0xb1) > 172);
+        }
+
+        it "ライン途中のパレット変更が反映される" {
+            let mut cpu = cpu(0x00);
+            write_solid_tile(&mut cpu, 0x8000, true);
+            cpu.mmu.write_byte(0xff47, 0xc0);
+            cpu.mmu.ppu.set_renderer(Renderer::Fifo);
+            cpu.mmu.write_byte(0xff40, 0x91);
+            // mode 2, 12 dots until the first pixel, then 80 pixels
+            cpu.mmu.run(80 + 12 + 80);
+            cpu.mmu.write_byte(0xff47, 0x00);
+            cpu.mmu.run(456);
+
+            let line = cpu.mmu.ppu.frame_buffer[0..160].to_vec();
+            assert_eq!(vec![0x000000; 80], line[0..80].to_vec());
+            assert_eq!(vec![WHITE; 80], line[80..160].to_vec());
+        }
+    }
+
+    describe "STAT" {
+        it "現在のモードを返す" {
+            let mut cpu = cpu(0x00);
+            assert_eq!(0, stat_mode(&cpu));
+            cpu.mmu.write_byte(0xff40, 0x91);
+            assert_eq!(2, stat_mode(&cpu));
+            cpu.mmu.run(80);
+            assert_eq!(3, stat_mode(&cpu));
+            cpu.mmu.run(172);
+            assert_eq!(0, stat_mode(&cpu));
+            cpu.mmu.run(204);
+            assert_eq!(2, stat_mode(&cpu));
+            run_until(&mut cpu, |cpu| cpu.mmu.read_byte(0xff44) == 144);
+            assert_eq!(1, stat_mode(&cpu));
+        }
+
+        it "LYC一致フラグを返す" {
+            let mut cpu = cpu(0x00);
+            cpu.mmu.write_byte(0xff45, 2);
+            cpu.mmu.write_byte(0xff40, 0x91);
+            assert_eq!(0x00, cpu.mmu.read_byte(0xff41) & 0x04);
+            run_until(&mut cpu, |cpu| cpu.mmu.read_byte(0xff44) == 2);
+            assert_eq!(0x04, cpu.mmu.read_byte(0xff41) & 0x04);
+            run_until(&mut cpu, |cpu| cpu.mmu.read_byte(0xff44) == 3);
+            assert_eq!(0x00, cpu.mmu.read_byte(0xff41) & 0x04);
+        }
+
+        it "書き込みでモードとLYCフラグは変わらない" {
+            let mut cpu = cpu(0x00);
+            cpu.mmu.write_byte(0xff40, 0x91);
+            cpu.mmu.write_byte(0xff41, 0xff);
+            assert_eq!(0xfe, cpu.mmu.read_byte(0xff41));
+        }
+
+        it "割り込みが無効なら要求しない" {
+            let mut cpu = cpu(0x00);
+            cpu.mmu.write_byte(0xff0f, 0x00);
+            cpu.mmu.write_byte(0xff40, 0x91);
+            for _ in 0..70224 / 4 {
+                cpu.mmu.run(4);
+            }
+            assert!(!stat_interrupt(&cpu));
+        }
+
+        #[rstest(stat, mode,
+            case(0x08, 0),
+            case(0x10, 1),
+            case(0x20, 2),
+        )]
+        fn 有効なモードで割り込みを要求する(stat: u8, mode: u8) {
+            let mut cpu = cpu(0x00);
+            cpu.mmu.write_byte(0xff41, stat);
+            cpu.mmu.write_byte(0xff40, 0x91);
+            cpu.mmu.run(4);
+            cpu.mmu.write_byte(0xff0f, 0x00);
+            run_until(&mut cpu, stat_interrupt);
+            assert_eq!(mode, stat_mode(&cpu));
+        }
+
+        it "HBlankの直後のモード2割り込みはブロックされる" {
+            let mut cpu = cpu(0x00);
+            cpu.mmu.write_byte(0xff41, 0x28);
+            cpu.mmu.write_byte(0xff40, 0x91);
+            run_until(&mut cpu, |cpu| stat_mode(cpu) == 3);
+            cpu.mmu.write_byte(0xff0f, 0x00);
+            run_until(&mut cpu, |cpu| stat_mode(cpu) == 0);
+            assert!(stat_interrupt(&cpu));
+            cpu.mmu.write_byte(0xff0f, 0x00);
+            run_until(&mut cpu, |cpu| stat_mode(cpu) == 2);
+            assert!(!stat_interrupt(&cpu));
+        }
+
+        it "LYC一致中はHBlank割り込みがブロックされる" {
+            let mut cpu = cpu(0x00);
+            cpu.mmu.write_byte(0xff45, 1);
+            cpu.mmu.write_byte(0xff41, 0x48);
+            cpu.mmu.write_byte(0xff40, 0x91);
+            run_until(&mut cpu, |cpu| stat_mode(cpu) == 3);
+            cpu.mmu.write_byte(0xff0f, 0x00);
+            run_until(&mut cpu, |cpu| stat_mode(cpu) == 0);
+            assert!(stat_interrupt(&cpu));
+            run_until(&mut cpu, |cpu| cpu.mmu.read_byte(0xff44) == 1 && stat_mode(cpu) == 3);
+            cpu.mmu.write_byte(0xff0f, 0x00);
+            run_until(&mut cpu, |cpu| stat_mode(cpu) == 0);
+            assert!(!stat_interrupt(&cpu));
+        }
+    }
+
+    describe "ウィンドウ" {
+        #[rstest(renderer, wx,
+            case(Renderer::Scanline, 0),
+            case(Renderer::Scanline, 3),
+            case(Renderer::Scanline, 7),
+            case(Renderer::Scanline, 87),
+            case(Renderer::Fifo, 0),
+            case(Renderer::Fifo, 3),
+            case(Renderer::Fifo, 7),
+            case(Renderer::Fifo, 87),
+        )]
+        fn WXの位置から描画する(renderer: Renderer, wx: u8) {
+            let mut cpu = window_scene(0, wx);
+            let frame = render_lines(&mut cpu, renderer, |_, _| ());
+            assert!(frame == window_frame(wx, Some));
+        }
+
+        #[rstest(renderer, wx, ly, x, expected,
+            case(Renderer::Scanline, 3, 0, 0, [W, W, W, W, G, W, W, W, W, W, W, W, G, W, W, W]),
+            case(Renderer::Scanline, 3, 9, 0, [W, W, G, W, W, W, W, W, W, W, G, W, W, W, W, W]),
+            case(Renderer::Scanline, 87, 0, 72, [K, K, K, K, K, K, K, K, G, W, W, W, W, W, W, W]),
+            case(Renderer::Scanline, 166, 0, 144, [K, K, K, K, K, K, K, K, K, K, K, K, K, K, K, G]),
+            case(Renderer::Scanline, 166, 1, 0, [W, G, W, W, W, W, W, W, W, G, W, W, W, W, W, W]),
+            case(Renderer::Fifo, 3, 0, 0, [W, W, W, W, G, W, W, W, W, W, W, W, G, W, W, W]),
+            case(Renderer::Fifo, 3, 9, 0, [W, W, G, W, W, W, W, W, W, W, G, W, W, W, W, W]),
+            case(Renderer::Fifo, 87, 0, 72, [K, K, K, K, K, K, K, K, G, W, W, W, W, W, W, W]),
+            case(Renderer::Fifo, 166, 0, 144, [K, K, K, K, K, K, K, K, K, K, K, K, K, K, K, G]),
+            case(Renderer::Fifo, 166, 1, 0, [W, G, W, W, W, W, W, W, W, G, W, W, W, W, W, W]),
+        )]
+        fn 画素がリファレンスと一致する(
+            renderer: Renderer,
+            wx: u8,
+            ly: usize,
+            x: usize,
+            expected: [u32; 16],
+        ) {
+            let mut cpu = window_scene(0, wx);
+            let frame = render_lines(&mut cpu, renderer, |_, _| ());
+            let start = ly * 160 + x;
+            assert_eq!(expected.to_vec(), frame[start..start + 16].to_vec());
+        }
+
+        #[rstest(renderer,
+            case(Renderer::Scanline),
+            case(Renderer::Fifo),
+        )]
+        fn WXが166だと次のラインを覆う(renderer: Renderer) {
+            let mut cpu = window_scene(0, 166);
+            let frame = render_lines(&mut cpu, renderer, |cpu, ly| {
+                if ly == 1 {
+                    cpu.mmu.write_byte(0xff4b, 167);
+                }
+            });
+            let mut expected = window_frame(7, |y| if y == 1 { Some(1) } else { None });
+            expected[..160].copy_from_slice(&window_frame(166, Some)[..160]);
+            assert!(frame == expected);
+        }
+
+        #[rstest(renderer,
+            case(Renderer::Scanline),
+            case(Renderer::Fifo),
+        )]
+        fn WXが166より大きいと表示しない(renderer: Renderer) {
+            let mut cpu = window_scene(0, 167);
+            let frame = render_lines(&mut cpu, renderer, |_, _| ());
+            assert!(frame == window_frame(167, |_| None));
+        }
+
+        #[rstest(renderer,
+            case(Renderer::Scanline),
+            case(Renderer::Fifo),
+        )]
+        fn 無効にしたラインでは行カウンタが進まない(renderer: Renderer) {
+            let mut cpu = window_scene(20, 7);
+            let frame = render_lines(&mut cpu, renderer, |cpu, ly| match ly {
+                50 => cpu.mmu.write_byte(0xff40, 0xd1),
+                60 => cpu.mmu.write_byte(0xff40, 0xf1),
+                _ => (),
+            });
+            let expected = window_frame(7, |y| match y {
+                0..=19 | 50..=59 => None,
+                20..=49 => Some(y - 20),
+                _ => Some(y - 30),
+            });
+            assert!(frame == expected);
+        }
+
+        #[rstest(renderer,
+            case(Renderer::Scanline),
+            case(Renderer::Fifo),
+        )]
+        fn フレーム途中のWYとの一致で表示を始める(renderer: Renderer) {
+            let mut cpu = window_scene(255, 7);
+            let frame = render_lines(&mut cpu, renderer, |cpu, ly| match ly {
+                70 => cpu.mmu.write_byte(0xff4a, 70),
+                // moving WY after the trigger does not hide the window
+                100 => cpu.mmu.write_byte(0xff4a, 120),
+                _ => (),
+            });
+            let expected = window_frame(7, |y| if y >= 70 { Some(y - 70) } else { None });
+            assert!(frame == expected);
+        }
+
+        #[rstest(renderer,
+            case(Renderer::Scanline),
+            case(Renderer::Fifo),
+        )]
+        fn 通過済みのWYでは表示しない(renderer: Renderer) {
+            let mut cpu = window_scene(255, 7);
+            let frame = render_lines(&mut cpu, renderer, |cpu, ly| {
+                if ly == 50 {
+                    cpu.mmu.write_byte(0xff4a, 30);
+                }
+            });
+            assert!(frame == window_frame(7, |_| None));
+        }
+    }
+
+    describe "DMGのパレット" {
+        // BG tile 1 at x 0-7 and an OBP1 sprite with tile 1 at x 8-15
+        fn palette_scene(palette: Palette) -> CPU {
+            let mut cpu = cpu(0x00);
+            write_solid_tile(&mut cpu, 0x8010, true);
+            cpu.mmu.write_byte(0x9800, 0x01);
+            cpu.mmu.write_byte(0xfe00, 16);
+            cpu.mmu.write_byte(0xfe01, 16);
+            cpu.mmu.write_byte(0xfe02, 0x01);
+            cpu.mmu.write_byte(0xfe03, 0x10);
+            cpu.mmu.write_byte(0xff47, 0xe4);
+            cpu.mmu.write_byte(0xff49, 0x1b);
+            cpu.mmu.ppu.set_palette(palette);
+            cpu
+        }
+
+        it "初期値はグレー" {
+            let mut cpu = palette_scene(Palette::default());
+            let line = render_line(&mut cpu, 0x93);
+            assert_eq!(0x000000, line[0]);
+            assert_eq!(WHITE, line[8]);
+            assert_eq!(WHITE, line[16]);
+        }
+
+        #[rstest(palette,
+            case(Palette::GREEN),
+            case(Palette::POCKET),
+            case(Palette::CGB),
+        )]
+        fn プリセットの色で描画する(palette: Palette) {
+            let mut cpu = palette_scene(palette);
+            let line = render_line(&mut cpu, 0x93);
+            assert_eq!(palette.bg[3], line[0]);
+            assert_eq!(palette.obj1[0], line[8]);
+            assert_eq!(palette.bg[0], line[16]);
+        }
+
+        it "BGとOBJで別々の色を使う" {
+            let palette = Palette {
+                bg: [0x000001, 0x000002, 0x000003, 0x000004],
+                obj0: [0x000005, 0x000006, 0x000007, 0x000008],
+                obj1: [0x000009, 0x00000a, 0x00000b, 0x00000c],
+            };
+            let mut cpu = palette_scene(palette);
+            let line = render_line(&mut cpu, 0x93);
+            assert_eq!(0x000004, line[0]);
+            assert_eq!(0x000009, line[8]);
+            assert_eq!(0x000001, line[16]);
+
+            cpu.mmu.write_byte(0xff40, 0x00);
+            cpu.mmu.write_byte(0xfe03, 0x00);
+            cpu.mmu.write_byte(0xff48, 0xe4);
+            let line = render_line(&mut cpu, 0x93);
+            assert_eq!(0x000008, line[8]);
+        }
+
+        #[rstest(gamma, shades,
+            case(1.0, [0xffffff, 0xaaaaaa, 0x555555, 0x000000]),
+            case(2.0, [0xffffff, 0x717171, 0x1c1c1c, 0x000000]),
+        )]
+        fn ガンマで中間色を補正する(gamma: f64, shades: [u32; 4]) {
+            assert_eq!(Palette::uniform(shades), Palette::GRAY.with_gamma(gamma));
+        }
+
+        it "ガンマ補正はチャンネルごとにかかる" {
+            let palette = Palette::uniform([0x80ff00; 4]).with_gamma(2.0);
+            assert_eq!([0x40ff00; 4], palette.obj0);
+        }
+
+        #[rstest(renderer,
+            case(Renderer::Scanline),
+            case(Renderer::Fifo),
+        )]
+        fn BGが無効ならパレットの一番明るい色で塗る(renderer: Renderer) {
+            let mut cpu = palette_scene(Palette::GREEN.with_gamma(2.0));
+            cpu.mmu.ppu.set_renderer(renderer);
+            cpu.mmu.write_byte(0xff40, 0x80);
+            cpu.mmu.run(456);
+            let line = cpu.mmu.ppu.frame_buffer[0..160].to_vec();
+            assert_eq!(vec![Palette::GREEN.with_gamma(2.0).bg[0]; 160], line);
+        }
+
+        it "CGBモードでは使わない" {
+            let mut cpu = scene();
+            cpu.mmu.ppu.set_palette(Palette::GREEN);
+            let line = render_line(&mut cpu, 0x91);
+            assert_eq!(BLUE, line[0]);
+        }
+    }
+}
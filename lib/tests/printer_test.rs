@@ -0,0 +1,157 @@
+#[cfg(test)]
+extern crate rstest;
+extern crate speculate;
+
+use rstest::*;
+use speculate::speculate;
+
+use gameboy_rs_lib::{Printer, SerialDevice, PRINTOUT_WIDTH};
+
+// one band: 2 rows of 20 tiles
+const BAND_SIZE: usize = 0x280;
+
+// sends a packet and returns the alive and status bytes
+fn send(printer: &mut Printer, command: u8, compression: u8, data: &[u8]) -> (u8, u8) {
+    let len = data.len() as u16;
+    let header = [command, compression, len as u8, (len >> 8) as u8];
+    let checksum = header
+        .iter()
+        .chain(data.iter())
+        .fold(0u16, |sum, &b| sum.wrapping_add(b as u16));
+    let bytes = [0x88, 0x33]
+        .iter()
+        .chain(header.iter())
+        .chain(data.iter())
+        .chain([checksum as u8, (checksum >> 8) as u8].iter())
+        .copied()
+        .collect::<Vec<u8>>();
+    for b in bytes {
+        assert_eq!(0x00, printer.exchange(b));
+    }
+    (printer.exchange(0x00), printer.exchange(0x00))
+}
+
+// tiles with the same two bytes on every line
+fn band(lo: u8, hi: u8) -> Vec<u8> {
+    [lo, hi].repeat(BAND_SIZE / 2)
+}
+
+// sheets, margins, palette, exposure
+fn print(printer: &mut Printer, margins: u8, palette: u8) -> (u8, u8) {
+    send(printer, 0x02, 0x00, &[0x01, margins, palette, 0x40])
+}
+
+speculate! {
+    describe "プリンタ" {
+        it "パケットの最後に生存確認とステータスを返す" {
+            let mut printer = Printer::new();
+            assert_eq!((0x81, 0x00), send(&mut printer, 0x01, 0x00, &[]));
+            assert_eq!((0x81, 0x00), send(&mut printer, 0x0f, 0x00, &[]));
+        }
+
+        it "チェックサムが違うとエラーを返す" {
+            let mut printer = Printer::new();
+            for b in [0x88, 0x33, 0x0f, 0x00, 0x00, 0x00, 0x10, 0x00] {
+                printer.exchange(b);
+            }
+            assert_eq!(0x81, printer.exchange(0x00));
+            assert_eq!(0x01, printer.exchange(0x00));
+            assert_eq!((0x81, 0x00), send(&mut printer, 0x0f, 0x00, &[]));
+        }
+
+        it "データを受け取って印刷する" {
+            let mut printer = Printer::new();
+            send(&mut printer, 0x01, 0x00, &[]);
+            assert_eq!((0x81, 0x08), send(&mut printer, 0x04, 0x00, &band(0xff, 0x00)));
+            send(&mut printer, 0x04, 0x00, &[]);
+            assert_eq!(None, printer.take_printout());
+
+            assert_eq!((0x81, 0x02), print(&mut printer, 0x13, 0xe4));
+            let printout = printer.take_printout().unwrap();
+            assert_eq!(16, printout.height);
+            assert_eq!(vec![1; PRINTOUT_WIDTH * 16], printout.pixels);
+            assert_eq!(None, printer.take_printout());
+        }
+
+        it "印刷中はビジーを返す" {
+            let mut printer = Printer::new();
+            send(&mut printer, 0x04, 0x00, &band(0xff, 0x00));
+            print(&mut printer, 0x13, 0xe4);
+            let mut polls = 0;
+            while send(&mut printer, 0x0f, 0x00, &[]).1 & 0x02 != 0 {
+                polls += 1;
+                assert!(polls < 100);
+            }
+            assert_eq!((0x81, 0x00), send(&mut printer, 0x0f, 0x00, &[]));
+        }
+
+        #[rstest(palette, shade,
+            case(0xe4, 1),
+            case(0x1b, 2),
+            case(0x00, 1),
+        )]
+        fn パレットで濃さを決める(palette: u8, shade: u8) {
+            let mut printer = Printer::new();
+            send(&mut printer, 0x04, 0x00, &band(0xff, 0x00));
+            print(&mut printer, 0x01, palette);
+            assert_eq!(shade, printer.take_printout().unwrap().pixels[0]);
+        }
+
+        it "圧縮データを展開する" {
+            let mut printer = Printer::new();
+            // 0xff, 0x00 pairs as literals, then runs of 0xaa
+            let mut data = Vec::new();
+            for _ in 0..BAND_SIZE / 4 {
+                data.extend_from_slice(&[0x01, 0xff, 0x00]);
+            }
+            let mut rest = BAND_SIZE / 2;
+            while rest > 0 {
+                let n = rest.min(0x81);
+                data.extend_from_slice(&[0x80 | (n - 2) as u8, 0xaa]);
+                rest -= n;
+            }
+            send(&mut printer, 0x04, 0x01, &data);
+            print(&mut printer, 0x01, 0xe4);
+
+            let mut expected = Printer::new();
+            let mut raw = [0xff, 0x00].repeat(BAND_SIZE / 4);
+            raw.extend(vec![0xaa; BAND_SIZE / 2]);
+            send(&mut expected, 0x04, 0x00, &raw);
+            print(&mut expected, 0x01, 0xe4);
+            assert_eq!(expected.take_printout(), printer.take_printout());
+        }
+
+        it "初期化すると印刷途中の用紙を切り離す" {
+            let mut printer = Printer::new();
+            send(&mut printer, 0x04, 0x00, &band(0xff, 0x00));
+            print(&mut printer, 0x10, 0xe4);
+            assert_eq!(None, printer.take_printout());
+            send(&mut printer, 0x01, 0x00, &[]);
+            assert_eq!(16, printer.take_printout().unwrap().height);
+        }
+
+        it "終了時に印刷途中の用紙を取り出せる" {
+            let mut printer = Printer::new();
+            send(&mut printer, 0x04, 0x00, &band(0xff, 0x00));
+            print(&mut printer, 0x10, 0xe4);
+            printer.flush();
+            assert_eq!(16, printer.take_printout().unwrap().height);
+            printer.flush();
+            assert_eq!(None, printer.take_printout());
+        }
+
+        it "余白なしの印刷は1枚につなげる" {
+            let mut printer = Printer::new();
+            send(&mut printer, 0x04, 0x00, &band(0xff, 0x00));
+            print(&mut printer, 0x10, 0xe4);
+            assert_eq!(None, printer.take_printout());
+            send(&mut printer, 0x04, 0x00, &band(0xff, 0xff));
+            print(&mut printer, 0x03, 0xe4);
+
+            let printout = printer.take_printout().unwrap();
+            assert_eq!(32, printout.height);
+            assert_eq!(1, printout.pixels[0]);
+            assert_eq!(3, printout.pixels[PRINTOUT_WIDTH * 16]);
+        }
+    }
+}
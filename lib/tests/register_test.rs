@@ -13,8 +13,8 @@ use gameboy_rs_lib::cpu::register;
 speculate! {
     describe "レジスタ操作" {
         #[fixture(b=0b_10101111, c=0b_11001100)]
-        fn fixture(b: u8, c: u8) -> Registers {
-            let mut register = Registers::new();
+        fn fixture(b: u8, c: u8) -> Register {
+            let mut register = Register::new();
             register.b = b;
             register.c = c;
             return register;
@@ -24,7 +24,7 @@ speculate! {
             #[rstest(reg, expected,
                 case(Registers16::BC, 0b_10101111_11001100),
             )]
-            fn read_wordは対象16bitレジスタを読み取れる(fixture: Registers, reg: Registers16, expected: u16) {
+            fn read_wordは対象16bitレジスタを読み取れる(fixture: Register, reg: Registers16, expected: u16) {
                 assert_eq!(expected, fixture.read_word(reg));
             }
         }
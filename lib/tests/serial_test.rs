@@ -0,0 +1,232 @@
+#[cfg(test)]
+extern crate rstest;
+extern crate speculate;
+
+use rstest::*;
+use speculate::speculate;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use gameboy_rs_lib::{SerialCapture, SerialDevice, CPU};
+
+fn rom(program: &[u8], cgb_flag: u8) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+    rom[0x0143] = cgb_flag;
+    rom
+}
+
+fn cpu(cgb_flag: u8) -> CPU {
+    let mut cpu = CPU::new(rom(&[], cgb_flag)).unwrap();
+    cpu.init();
+    cpu.mmu.write_byte(0xff0f, 0x00);
+    cpu
+}
+
+// sends the bytes the way Blargg's test ROMs print
+fn print(cpu: &mut CPU, text: &str) {
+    for b in text.bytes() {
+        cpu.mmu.write_byte(0xff01, b);
+        cpu.mmu.write_byte(0xff02, 0x81);
+        cpu.mmu.run(4096);
+    }
+}
+
+fn serial_interrupt(cpu: &CPU) -> bool {
+    cpu.mmu.read_byte(0xff0f) & 0x08 != 0
+}
+
+// answers with a fixed byte and records what it received
+#[derive(Debug)]
+struct Echo {
+    reply: u8,
+    received: Arc<Mutex<Vec<u8>>>,
+}
+
+impl SerialDevice for Echo {
+    fn exchange(&mut self, data: u8) -> u8 {
+        self.received.lock().unwrap().push(data);
+        self.reply
+    }
+}
+
+// clocks one transfer from the other side
+#[derive(Debug)]
+struct Master {
+    data: Option<u8>,
+    received: Arc<Mutex<Vec<u8>>>,
+}
+
+impl SerialDevice for Master {
+    fn exchange(&mut self, _data: u8) -> u8 {
+        0xff
+    }
+
+    fn poll_external(&mut self, data: u8) -> Option<u8> {
+        let v = self.data.take()?;
+        self.received.lock().unwrap().push(data);
+        Some(v)
+    }
+}
+
+// adds up the ticks it is run for
+#[derive(Debug)]
+struct Clock {
+    ticks: Arc<Mutex<u64>>,
+}
+
+impl SerialDevice for Clock {
+    fn exchange(&mut self, _data: u8) -> u8 {
+        0xff
+    }
+
+    fn run(&mut self, ticks: u32) {
+        *self.ticks.lock().unwrap() += ticks as u64;
+    }
+}
+
+speculate! {
+    describe "シリアル転送" {
+        it "内部クロックでは8192Hzで8ビット転送する" {
+            let mut cpu = cpu(0x00);
+            cpu.mmu.write_byte(0xff01, 0x42);
+            cpu.mmu.write_byte(0xff02, 0x81);
+            cpu.mmu.run(4096 - 4);
+            assert_eq!(0xff, cpu.mmu.read_byte(0xff02));
+            assert!(!serial_interrupt(&cpu));
+
+            cpu.mmu.run(4);
+            assert_eq!(0x7f, cpu.mmu.read_byte(0xff02));
+            assert!(serial_interrupt(&cpu));
+        }
+
+        it "未接続なら0xffを受け取る" {
+            let mut cpu = cpu(0x00);
+            cpu.mmu.write_byte(0xff01, 0x42);
+            cpu.mmu.write_byte(0xff02, 0x81);
+            cpu.mmu.run(4096);
+            assert_eq!(0xff, cpu.mmu.read_byte(0xff01));
+        }
+
+        it "接続したデバイスとバイトを交換する" {
+            let received = Arc::new(Mutex::new(Vec::new()));
+            let mut cpu = cpu(0x00);
+            cpu.mmu.serial.set_device(Box::new(Echo {
+                reply: 0x24,
+                received: received.clone(),
+            }));
+            cpu.mmu.write_byte(0xff01, 0x42);
+            cpu.mmu.write_byte(0xff02, 0x81);
+            cpu.mmu.run(4096);
+            assert_eq!(0x24, cpu.mmu.read_byte(0xff01));
+            assert_eq!(vec![0x42], *received.lock().unwrap());
+        }
+
+        it "外部クロックでは相手がいないと終わらない" {
+            let mut cpu = cpu(0x00);
+            cpu.mmu.write_byte(0xff02, 0x80);
+            for _ in 0..100 {
+                cpu.mmu.run(4096);
+            }
+            assert_eq!(0xfe, cpu.mmu.read_byte(0xff02));
+            assert!(!serial_interrupt(&cpu));
+        }
+
+        it "外部クロックでは相手のクロックで転送する" {
+            let received = Arc::new(Mutex::new(Vec::new()));
+            let mut cpu = cpu(0x00);
+            cpu.mmu.serial.set_device(Box::new(Master {
+                data: Some(0x24),
+                received: received.clone(),
+            }));
+            cpu.mmu.write_byte(0xff01, 0x42);
+            cpu.mmu.run(4);
+            assert!(received.lock().unwrap().is_empty());
+
+            cpu.mmu.write_byte(0xff02, 0x80);
+            cpu.mmu.run(4);
+            assert_eq!(0x24, cpu.mmu.read_byte(0xff01));
+            assert_eq!(0x7e, cpu.mmu.read_byte(0xff02));
+            assert!(serial_interrupt(&cpu));
+            assert_eq!(vec![0x42], *received.lock().unwrap());
+        }
+
+        it "転送していなくてもデバイスに経過時間を伝える" {
+            let ticks = Arc::new(Mutex::new(0));
+            let mut cpu = cpu(0x00);
+            cpu.mmu.serial.set_device(Box::new(Clock {
+                ticks: ticks.clone(),
+            }));
+            cpu.mmu.run(4);
+            cpu.mmu.run(8);
+            assert_eq!(12, *ticks.lock().unwrap());
+        }
+
+        #[rstest(cgb_flag, clocks,
+            case(0x00, 4096),
+            case(0x80, 128),
+        )]
+        fn CGBでは高速クロックを選べる(cgb_flag: u8, clocks: u32) {
+            let mut cpu = cpu(cgb_flag);
+            cpu.mmu.write_byte(0xff02, 0x83);
+            cpu.mmu.run(clocks - 4);
+            assert!(!serial_interrupt(&cpu));
+            cpu.mmu.run(4);
+            assert!(serial_interrupt(&cpu));
+        }
+    }
+
+    describe "割り込みベクタ" {
+        #[rstest(flag, vector,
+            case(0x08, 0x0058),
+            case(0x10, 0x0060),
+        )]
+        fn 割り込みごとのアドレスに飛ぶ(flag: u8, vector: usize) {
+            // ei; nop
+            let mut rom = rom(&[0xfb, 0x00], 0x00);
+            // ld a, 0x01; ldh (0x80), a
+            rom[vector..vector + 4].copy_from_slice(&[0x3e, 0x01, 0xe0, 0x80]);
+            let mut cpu = CPU::new(rom).unwrap();
+            cpu.init();
+            cpu.mmu.write_byte(0xffff, flag);
+            cpu.mmu.write_byte(0xff0f, flag);
+            for _ in 0..5 {
+                cpu.run().unwrap();
+            }
+            assert_eq!(0x01, cpu.mmu.read_byte(0xff80));
+        }
+    }
+
+    describe "シリアル出力の記録" {
+        it "送られた文字列を読める" {
+            let capture = SerialCapture::new();
+            let mut cpu = cpu(0x00);
+            cpu.mmu.serial.set_device(Box::new(capture.clone()));
+            print(&mut cpu, "cpu_instrs\n\nPassed");
+            assert_eq!("cpu_instrs\n\nPassed", capture.output());
+            assert_eq!(0xff, cpu.mmu.read_byte(0xff01));
+        }
+
+        it "別スレッドで動かしても記録できる" {
+            let capture = SerialCapture::new();
+            let mut cpu = cpu(0x00);
+            cpu.mmu.serial.set_device(Box::new(capture.clone()));
+            thread::spawn(move || print(&mut cpu, "Passed"))
+                .join()
+                .unwrap();
+            assert_eq!("Passed", capture.output());
+        }
+
+        it "前回からの出力を取り出せる" {
+            let capture = SerialCapture::new();
+            let mut cpu = cpu(0x00);
+            cpu.mmu.serial.set_device(Box::new(capture.clone()));
+            print(&mut cpu, "01:ok ");
+            assert_eq!(b"01:ok ".to_vec(), capture.take_output());
+            print(&mut cpu, "02:ok ");
+            assert_eq!(b"02:ok ".to_vec(), capture.take_output());
+            assert_eq!("", capture.output());
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample};
+use log::warn;
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+// 100ms of stereo samples between the emulator and the sound device
+const BUFFER_MILLIS: usize = 100;
+
+pub struct Audio {
+    _stream: cpal::Stream,
+    buffer: Arc<Mutex<VecDeque<i16>>>,
+    capacity: usize,
+    sample_rate: u32,
+    resampler: Resampler,
+}
+
+// stretches the APU output by a ratio close to 1 so that the device buffer neither runs dry nor
+// overflows, while the APU (and the WAV dump) keep the nominal rate
+struct Resampler {
+    // device samples per APU sample
+    ratio: f64,
+    // position of the next output frame, relative to `last`
+    position: f64,
+    // the last stereo frame of the previous call
+    last: [i16; 2],
+}
+
+impl Resampler {
+    fn process(&mut self, samples: &[i16]) -> Vec<i16> {
+        let frames = samples.len() / 2;
+        let step = 1.0 / self.ratio;
+        let mut out = Vec::with_capacity(samples.len() + 4);
+        while self.position < frames as f64 {
+            let i = self.position as usize;
+            let t = self.position - i as f64;
+            for c in 0..2 {
+                let a = if i == 0 {
+                    self.last[c]
+                } else {
+                    samples[(i - 1) * 2 + c]
+                } as f64;
+                let b = samples[i * 2 + c] as f64;
+                out.push((a + (b - a) * t).round() as i16);
+            }
+            self.position += step;
+        }
+        self.position -= frames as f64;
+        if frames > 0 {
+            self.last = [samples[frames * 2 - 2], samples[frames * 2 - 1]];
+        }
+        out
+    }
+}
+
+impl Audio {
+    pub fn new() -> Result<Audio, String> {
+        let host = cpal::default_host();
+        let device = host
+            .default_output_device()
+            .ok_or_else(|| "no output device".to_string())?;
+        let supported = device.default_output_config().map_err(|e| e.to_string())?;
+        let sample_format = supported.sample_format();
+        let config: cpal::StreamConfig = supported.into();
+        let sample_rate = config.sample_rate.0;
+
+        let capacity = sample_rate as usize * 2 * BUFFER_MILLIS / 1000;
+        let buffer = Arc::new(Mutex::new(VecDeque::with_capacity(capacity)));
+
+        let stream = match sample_format {
+            cpal::SampleFormat::F32 => Audio::build_stream::<f32>(&device, &config, &buffer),
+            cpal::SampleFormat::I16 => Audio::build_stream::<i16>(&device, &config, &buffer),
+            cpal::SampleFormat::U16 => Audio::build_stream::<u16>(&device, &config, &buffer),
+            f => return Err(format!("unsupported sample format: {}", f)),
+        }?;
+        stream.play().map_err(|e| e.to_string())?;
+
+        Ok(Audio {
+            _stream: stream,
+            buffer,
+            capacity,
+            sample_rate,
+            resampler: Resampler {
+                ratio: 1.0,
+                position: 0.0,
+                last: [0; 2],
+            },
+        })
+    }
+
+    fn build_stream<T>(
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        buffer: &Arc<Mutex<VecDeque<i16>>>,
+    ) -> Result<cpal::Stream, String>
+    where
+        T: SizedSample + FromSample<i16>,
+    {
+        let channels = config.channels as usize;
+        let buffer = buffer.clone();
+        device
+            .build_output_stream(
+                config,
+                move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                    let mut buffer = buffer.lock().unwrap();
+                    for frame in data.chunks_mut(channels) {
+                        // silence on underrun
+                        let left = buffer.pop_front().unwrap_or(0);
+                        let right = buffer.pop_front().unwrap_or(0);
+                        for (i, sample) in frame.iter_mut().enumerate() {
+                            *sample = T::from_sample(match i {
+                                0 => left,
+                                1 => right,
+                                _ => 0,
+                            });
+                        }
+                    }
+                },
+                |e| warn!("audio: {}", e),
+                None,
+            )
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // samples queued but not yet played
+    pub fn len(&self) -> usize {
+        self.buffer.lock().unwrap().len()
+    }
+
+    pub fn set_rate_ratio(&mut self, ratio: f64) {
+        self.resampler.ratio = ratio;
+    }
+
+    // interleaved stereo samples; the oldest ones are dropped when the device falls behind
+    pub fn push(&mut self, samples: &[i16]) {
+        let samples = self.resampler.process(samples);
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.extend(samples);
+        if buffer.len() > self.capacity {
+            let overflow = (buffer.len() - self.capacity) & !1;
+            buffer.drain(..overflow);
+        }
+    }
+}
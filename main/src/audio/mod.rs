@@ -0,0 +1,75 @@
+use log::warn;
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path;
+
+#[cfg(feature = "audio")]
+mod device;
+
+#[cfg(feature = "audio")]
+pub use self::device::Audio;
+
+// built without the sound device; no value exists, so the methods are never called
+#[cfg(not(feature = "audio"))]
+pub enum Audio {}
+
+#[cfg(not(feature = "audio"))]
+impl Audio {
+    pub fn new() -> Result<Audio, String> {
+        Err("built without the audio feature".to_string())
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        match *self {}
+    }
+
+    pub fn capacity(&self) -> usize {
+        match *self {}
+    }
+
+    pub fn len(&self) -> usize {
+        match *self {}
+    }
+
+    pub fn set_rate_ratio(&mut self, _ratio: f64) {
+        match *self {}
+    }
+
+    pub fn push(&mut self, _samples: &[i16]) {
+        match *self {}
+    }
+}
+
+pub struct AudioDump {
+    writer: hound::WavWriter<BufWriter<File>>,
+}
+
+impl AudioDump {
+    pub fn new(p: &path::Path, sample_rate: u32) -> Result<AudioDump, String> {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let writer = hound::WavWriter::create(p, spec).map_err(|e| e.to_string())?;
+        Ok(AudioDump { writer })
+    }
+
+    pub fn push(&mut self, samples: &[i16]) {
+        for s in samples {
+            if let Err(e) = self.writer.write_sample(*s) {
+                warn!("audio dump: {}", e);
+                return;
+            }
+        }
+    }
+
+    // keeps the header valid even if the process is killed
+    pub fn flush(&mut self) {
+        if let Err(e) = self.writer.flush() {
+            warn!("audio dump: {}", e);
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use gameboy_rs_lib::SerialDevice;
+use log::{info, warn};
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+// sent by both sides on connect
+const HANDSHAKE: &[u8; 4] = b"GBLK";
+
+// messages are a kind, the sequence number of the transfer, a data byte and the cycle count
+// of the sender (u64 LE)
+const MESSAGE_LEN: usize = 11;
+// a transfer clocked by the sender (master)
+const CLOCK: u8 = 0x01;
+// the answer of the side clocked by the peer (slave)
+const REPLY: u8 = 0x02;
+// the slave wasn't waiting for the external clock when its emulation reached the transfer
+const IDLE: u8 = 0x03;
+
+#[derive(Debug, Clone, Copy)]
+struct Message {
+    kind: u8,
+    seq: u8,
+    data: u8,
+    cycles: u64,
+}
+
+// link cable to another gameboy-rs over TCP
+//
+// both sides count the CPU ticks emulated since they connected. A CLOCK carries the master's
+// count, and the slave answers it once its own count reaches it: with its byte if it was
+// waiting for the external clock at that point, IDLE otherwise. The master stops until then,
+// so a slower slave holds it back rather than missing the transfer.
+//
+// what this doesn't cover:
+// - a slave that is already past the CLOCK's count when it arrives answers late; it still
+//   replies if it has been waiting since before that count, but its transfer completes at
+//   its own later time, and a transfer it gave up on in the meantime is lost
+// - when both sides clock at once, each takes the other's byte without checking whose
+//   count came first
+// - the counts are CPU ticks, so they drift apart while only one side runs in CGB double
+//   speed
+// - the master stops for as long as the peer doesn't emulate, until the connection closes
+#[derive(Debug)]
+pub struct Link {
+    stream: TcpStream,
+    messages: Receiver<Message>,
+    // CPU ticks emulated since connecting
+    cycles: u64,
+    // the last SC write, which is when a wait for the external clock starts
+    waiting_since: u64,
+    // sequence number of the last transfer this side clocked
+    seq: u8,
+    // the peer's answer to it, once it arrives; None on IDLE
+    reply: Option<Option<u8>>,
+    // a transfer clocked by the peer and not answered yet
+    peer_clock: Option<Message>,
+    connected: bool,
+}
+
+impl Link {
+    // `addr` is host:port, or a port to listen on localhost only
+    pub fn listen(addr: &str) -> io::Result<Link> {
+        let listener = match addr.parse::<u16>() {
+            Ok(port) => TcpListener::bind(("127.0.0.1", port))?,
+            Err(_) => TcpListener::bind(addr)?,
+        };
+        info!("link: waiting on {}", listener.local_addr()?);
+        Link::accept(&listener)
+    }
+
+    pub fn accept(listener: &TcpListener) -> io::Result<Link> {
+        let (stream, addr) = listener.accept()?;
+        info!("link: connected from {}", addr);
+        Link::new(stream)
+    }
+
+    pub fn connect(addr: &str) -> io::Result<Link> {
+        let stream = TcpStream::connect(addr)?;
+        info!("link: connected to {}", addr);
+        Link::new(stream)
+    }
+
+    fn new(mut stream: TcpStream) -> io::Result<Link> {
+        stream.set_nodelay(true)?;
+        stream.write_all(HANDSHAKE)?;
+        let mut handshake = [0; 4];
+        stream.read_exact(&mut handshake)?;
+        if &handshake != HANDSHAKE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the peer is not a gameboy-rs link",
+            ));
+        }
+
+        // the reader thread makes polling for the master's clock cheap
+        let (tx, messages) = mpsc::channel();
+        let mut reader = stream.try_clone()?;
+        thread::spawn(move || {
+            let mut buf = [0; MESSAGE_LEN];
+            while reader.read_exact(&mut buf).is_ok() {
+                let message = Message {
+                    kind: buf[0],
+                    seq: buf[1],
+                    data: buf[2],
+                    cycles: u64::from_le_bytes(buf[3..].try_into().unwrap()),
+                };
+                if tx.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Link {
+            stream,
+            messages,
+            cycles: 0,
+            waiting_since: 0,
+            seq: 0,
+            reply: None,
+            peer_clock: None,
+            connected: true,
+        })
+    }
+
+    fn send(&mut self, kind: u8, seq: u8, data: u8) {
+        let mut buf = [0; MESSAGE_LEN];
+        buf[..3].copy_from_slice(&[kind, seq, data]);
+        buf[3..].copy_from_slice(&self.cycles.to_le_bytes());
+        if let Err(e) = self.stream.write_all(&buf) {
+            self.disconnect(&e.to_string());
+        }
+    }
+
+    fn disconnect(&mut self, reason: &str) {
+        warn!("link: disconnected: {}", reason);
+        self.connected = false;
+    }
+
+    fn receive(&mut self, message: Message) {
+        match message.kind {
+            CLOCK => self.peer_clock = Some(message),
+            REPLY if message.seq == self.seq => self.reply = Some(Some(message.data)),
+            IDLE if message.seq == self.seq => self.reply = Some(None),
+            _ => (),
+        }
+    }
+
+    // handles the messages that have arrived so far
+    fn receive_all(&mut self) {
+        loop {
+            match self.messages.try_recv() {
+                Ok(message) => self.receive(message),
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.disconnect("closed by peer");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl SerialDevice for Link {
+    fn exchange(&mut self, data: u8) -> u8 {
+        if !self.connected {
+            return 0xff;
+        }
+        self.seq = self.seq.wrapping_add(1);
+        self.reply = None;
+        self.send(CLOCK, self.seq, data);
+        loop {
+            // without a slave waiting for the clock, nothing is on the line
+            if let Some(reply) = self.reply.take() {
+                return reply.unwrap_or(0xff);
+            }
+            // both sides clocked at once; each gets the other's byte
+            if let Some(clock) = self.peer_clock.take() {
+                return clock.data;
+            }
+            if !self.connected {
+                return 0xff;
+            }
+            match self.messages.recv() {
+                Ok(message) => self.receive(message),
+                Err(_) => self.disconnect("closed by peer"),
+            }
+        }
+    }
+
+    fn poll_external(&mut self, data: u8) -> Option<u8> {
+        if !self.connected {
+            return None;
+        }
+        let clock = match self.peer_clock {
+            Some(clock) if clock.cycles <= self.cycles => clock,
+            _ => return None,
+        };
+        self.peer_clock = None;
+        // arrived after this side had moved on; it wasn't waiting back then
+        if clock.cycles < self.waiting_since {
+            self.send(IDLE, clock.seq, 0xff);
+            return None;
+        }
+        self.send(REPLY, clock.seq, data);
+        Some(clock.data)
+    }
+
+    fn run(&mut self, ticks: u32) {
+        if !self.connected {
+            return;
+        }
+        // came due during the last step, and the Game Boy didn't take it
+        if let Some(clock) = self.peer_clock {
+            if clock.cycles <= self.cycles {
+                self.peer_clock = None;
+                self.send(IDLE, clock.seq, 0xff);
+            }
+        }
+        self.cycles += ticks as u64;
+        self.receive_all();
+    }
+
+    fn control_written(&mut self) {
+        self.waiting_since = self.cycles;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::Sender;
+    use std::time::Duration;
+
+    // a step of 1000 ticks every millisecond keeps the slave near the master
+    const STEP: u32 = 1000;
+
+    // emulates until `cycles`; with `data`, the Game Boy writes SC to wait for the external
+    // clock and stops at the first transfer
+    fn emulate(link: &mut Link, cycles: u64, data: Option<u8>) -> Option<u8> {
+        if data.is_some() {
+            link.control_written();
+        }
+        while link.cycles < cycles {
+            link.run(STEP);
+            if let Some(data) = data {
+                if let Some(v) = link.poll_external(data) {
+                    return Some(v);
+                }
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        None
+    }
+
+    fn slave(port: u16, done: Sender<u8>) {
+        let mut link = Link::connect(&format!("127.0.0.1:{}", port)).unwrap();
+        done.send(emulate(&mut link, u64::MAX, Some(0x24)).unwrap())
+            .unwrap();
+        // busy until 20000, whenever the master's clock at 15000 arrives
+        emulate(&mut link, 20_000, None);
+        done.send(emulate(&mut link, u64::MAX, Some(0x66)).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (done_tx, done) = mpsc::channel();
+        let handle = thread::spawn(move || slave(port, done_tx));
+        let mut master = Link::accept(&listener).unwrap();
+
+        master.run(5_000);
+        assert_eq!(0x24, master.exchange(0x42));
+        assert_eq!(0x42, done.recv().unwrap());
+
+        master.run(10_000);
+        assert_eq!(0xff, master.exchange(0x11));
+
+        master.run(15_000);
+        assert_eq!(0x66, master.exchange(0x77));
+        assert_eq!(0x77, done.recv().unwrap());
+        handle.join().unwrap();
+    }
+}
@@ -1,23 +1,40 @@
 extern crate clap;
+#[cfg(feature = "audio")]
+extern crate cpal;
 extern crate env_logger;
 extern crate gameboy_rs_lib;
+extern crate hound;
 extern crate log;
 extern crate minifb;
+extern crate png;
 
-use log::info;
+mod audio;
+mod link;
+mod printer;
+mod timing;
+
+use log::{error, info, warn};
 use minifb::{Key, KeyRepeat, Scale, Window, WindowOptions};
 
-use std::fs::File;
-use std::io::Read;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
 use std::path;
-use std::{thread, time};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use audio::{Audio, AudioDump};
 use gameboy_rs_lib::cpu::CPU;
 use gameboy_rs_lib::joypad::KeyInput;
+use gameboy_rs_lib::{Palette, Renderer, SerialCapture};
+use link::Link;
+use printer::PngPrinter;
+use timing::{Pacer, SyncMode};
 
 const SCREEN_WIDTH: usize = 160;
 const SCREEN_HEIGHT: usize = 144;
 const CPU_CYCLES_PER_FRAME: u32 = 70224;
+// flush battery-backed RAM every 5 seconds
+const FRAMES_PER_SAVE: u32 = 60 * 5;
 
 fn key(key: Key) -> Option<KeyInput> {
     match key {
@@ -42,12 +59,126 @@ fn open_rom_file(filepath: &str) -> Vec<u8> {
     data
 }
 
-fn sleep(now: time::Instant) {
-    // elapsed time per frame at 60fps
-    let wait = time::Duration::from_micros(1 / 60 * 1000 * 1000);
-    let elapsed = now.elapsed();
-    if wait > elapsed {
-        thread::sleep(wait - elapsed);
+fn save_file_path(rom_file: &str, save_file: Option<&str>) -> path::PathBuf {
+    match save_file {
+        Some(f) => path::PathBuf::from(f),
+        None => path::PathBuf::from(rom_file).with_extension("sav"),
+    }
+}
+
+// battery backed RAM (and the MBC3 clock) kept next to the ROM
+struct SaveFile {
+    path: path::PathBuf,
+    // contents last read or written, to skip writes when nothing changed
+    saved: Vec<u8>,
+}
+
+impl SaveFile {
+    fn new(path: path::PathBuf) -> SaveFile {
+        SaveFile {
+            path,
+            saved: Vec::new(),
+        }
+    }
+
+    fn load(&mut self, cpu: &mut CPU) {
+        if !cpu.mmu.cartridge.has_battery() {
+            return;
+        }
+        match fs::read(&self.path) {
+            Ok(data) => {
+                info!("save: {} size: {}", self.path.display(), data.len());
+                if let Some(timestamp) = cpu.mmu.cartridge.load_ram(&data) {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or(0);
+                    cpu.mmu.cartridge.advance_rtc(now.saturating_sub(timestamp));
+                }
+                self.saved = data;
+            }
+            Err(e) => info!("save: {} {}", self.path.display(), e),
+        }
+    }
+
+    // writes a temporary file and renames it over the save, so a crash never leaves it truncated
+    fn write(&mut self, cpu: &CPU) {
+        if !cpu.mmu.cartridge.has_battery() {
+            return;
+        }
+        let data = cpu.mmu.cartridge.save_ram();
+        if data == self.saved {
+            return;
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let result = fs::write(&tmp, &data).and_then(|_| fs::rename(&tmp, &self.path));
+        match result {
+            Ok(()) => self.saved = data,
+            Err(e) => warn!("save: {} {}", self.path.display(), e),
+        }
+    }
+}
+
+// also run periodically, so little is lost if the process is killed
+fn save(cpu: &CPU, save_file: &mut SaveFile, audio_dump: &mut Option<AudioDump>) {
+    save_file.write(cpu);
+    if let Some(audio_dump) = audio_dump.as_mut() {
+        audio_dump.flush();
+    }
+}
+
+// a preset name, or 4 (all palettes) or 12 (BG, OBJ0, OBJ1) comma separated RRGGBB colors
+fn parse_palette(s: &str) -> Option<Palette> {
+    match s {
+        "gray" => return Some(Palette::GRAY),
+        "green" => return Some(Palette::GREEN),
+        "pocket" => return Some(Palette::POCKET),
+        "cgb" => return Some(Palette::CGB),
+        _ => (),
+    }
+
+    let colors = s
+        .split(',')
+        .map(|c| u32::from_str_radix(c.trim().trim_start_matches('#'), 16).ok())
+        .collect::<Option<Vec<u32>>>()?;
+    if colors.iter().any(|&c| c > 0xffffff) {
+        return None;
+    }
+    let shades = |i: usize| [colors[i], colors[i + 1], colors[i + 2], colors[i + 3]];
+    match colors.len() {
+        4 => Some(Palette::uniform(shades(0))),
+        12 => Some(Palette {
+            bg: shades(0),
+            obj0: shades(4),
+            obj1: shades(8),
+        }),
+        _ => None,
+    }
+}
+
+// the CPU keeps locked up after an error, so the screen stays as the hardware would show it
+fn run(cpu: &mut CPU) -> u32 {
+    cpu.run().unwrap_or_else(|e| {
+        error!("cpu: {}", e);
+        4
+    })
+}
+
+// moves the samples of the last frame to the sound device and the WAV dump
+fn output_audio(cpu: &mut CPU, audio: &mut Option<Audio>, audio_dump: &mut Option<AudioDump>) {
+    let mut samples = [0; 4096];
+    loop {
+        let n = cpu.mmu.apu.drain_samples(&mut samples);
+        if n == 0 {
+            break;
+        }
+        if let Some(audio) = audio {
+            audio.push(&samples[..n]);
+        }
+        if let Some(audio_dump) = audio_dump {
+            audio_dump.push(&samples[..n]);
+        }
     }
 }
 
@@ -67,20 +198,182 @@ fn main() {
                 .required(false)
                 .long("bios"),
         )
+        .arg(
+            clap::Arg::with_name("save")
+                .takes_value(true)
+                .required(false)
+                .long("save"),
+        )
+        .arg(
+            clap::Arg::with_name("audio-dump")
+                .takes_value(true)
+                .required(false)
+                .long("audio-dump"),
+        )
+        .arg(
+            clap::Arg::with_name("sync")
+                .takes_value(true)
+                .required(false)
+                .possible_values(&["audio", "clock"])
+                .default_value("audio")
+                .long("sync"),
+        )
+        .arg(
+            clap::Arg::with_name("renderer")
+                .takes_value(true)
+                .required(false)
+                .possible_values(&["scanline", "fifo"])
+                .default_value("scanline")
+                .long("renderer"),
+        )
+        .arg(
+            clap::Arg::with_name("palette")
+                .takes_value(true)
+                .required(false)
+                .default_value("gray")
+                .long("palette"),
+        )
+        .arg(
+            clap::Arg::with_name("gamma")
+                .takes_value(true)
+                .required(false)
+                .long("gamma"),
+        )
+        .arg(
+            clap::Arg::with_name("link-listen")
+                .takes_value(true)
+                .required(false)
+                .conflicts_with("link-connect")
+                .long("link-listen"),
+        )
+        .arg(
+            clap::Arg::with_name("link-connect")
+                .takes_value(true)
+                .required(false)
+                .long("link-connect"),
+        )
+        .arg(
+            clap::Arg::with_name("printer")
+                .takes_value(true)
+                .required(false)
+                .conflicts_with_all(&["link-listen", "link-connect"])
+                .long("printer"),
+        )
         .arg(
             clap::Arg::with_name("headless")
                 .takes_value(false)
                 .required(false)
                 .long("headless"),
         )
+        .arg(
+            clap::Arg::with_name("frames")
+                .takes_value(true)
+                .required(false)
+                .long("frames"),
+        )
         .get_matches();
 
     let opt_headless = matches.is_present("headless");
     let opt_bios = matches.is_present("bios");
     let rom_file = matches.value_of("rom").unwrap();
 
+    let mut save_file = SaveFile::new(save_file_path(rom_file, matches.value_of("save")));
+
     let rom = open_rom_file(rom_file);
-    let mut cpu = CPU::new(rom);
+    let mut cpu = match CPU::new(rom) {
+        Ok(cpu) => cpu,
+        Err(e) => {
+            error!("rom: {} {}", rom_file, e);
+            process::exit(1);
+        }
+    };
+
+    let header = cpu.mmu.cartridge.header();
+    info!(
+        "title: {} type: {:02x} rom: {} ram: {} version: {}",
+        header.title, header.cartridge_type, header.rom_size, header.ram_size, header.version
+    );
+    if let Err(e) = header.verify() {
+        warn!("rom: {} {}", rom_file, e);
+    }
+    save_file.load(&mut cpu);
+
+    if matches.value_of("renderer") == Some("fifo") {
+        cpu.mmu.ppu.set_renderer(Renderer::Fifo);
+    }
+
+    let gamma = match matches.value_of("gamma").map(str::parse::<f64>) {
+        Some(Ok(g)) if g > 0.0 => g,
+        None => 1.0,
+        Some(_) => {
+            error!(
+                "gamma: {} is not a positive number",
+                matches.value_of("gamma").unwrap()
+            );
+            process::exit(1);
+        }
+    };
+    // stop after this many frames, so --headless test runs end on their own
+    let frame_limit = match matches.value_of("frames").map(str::parse::<u64>) {
+        Some(Ok(n)) => Some(n),
+        None => None,
+        Some(Err(_)) => {
+            error!(
+                "frames: {} is not a frame count",
+                matches.value_of("frames").unwrap()
+            );
+            process::exit(1);
+        }
+    };
+    let running = |total_frames: u64| !matches!(frame_limit, Some(n) if total_frames >= n);
+
+    let palette = matches.value_of("palette").unwrap();
+    match parse_palette(palette) {
+        Some(p) => cpu.mmu.ppu.set_palette(p.with_gamma(gamma)),
+        None => {
+            error!(
+                "palette: {} is not a preset or a list of RRGGBB colors",
+                palette
+            );
+            process::exit(1);
+        }
+    }
+
+    let link = if let Some(addr) = matches.value_of("link-listen") {
+        Some(Link::listen(addr))
+    } else {
+        matches.value_of("link-connect").map(Link::connect)
+    };
+    match link {
+        Some(Ok(link)) => cpu.mmu.serial.set_device(Box::new(link)),
+        Some(Err(e)) => {
+            error!("link: {}", e);
+            process::exit(1);
+        }
+        None => (),
+    }
+
+    if let Some(dir) = matches.value_of("printer") {
+        match PngPrinter::new(path::Path::new(dir)) {
+            Ok(printer) => cpu.mmu.serial.set_device(Box::new(printer)),
+            Err(e) => {
+                error!("printer: {} {}", dir, e);
+                process::exit(1);
+            }
+        }
+    }
+
+    // test ROMs report their results over the serial port
+    let serial_in_use = ["link-listen", "link-connect", "printer"]
+        .iter()
+        .any(|a| matches.is_present(a));
+    let capture = if opt_headless && !serial_in_use {
+        let capture = SerialCapture::new();
+        cpu.mmu.serial.set_device(Box::new(capture.clone()));
+        Some(capture)
+    } else {
+        None
+    };
 
     if opt_bios {
         let bios_file = matches.value_of("bios").unwrap();
@@ -90,17 +383,71 @@ fn main() {
         cpu.init();
     }
 
+    // no sound device on headless machines; the WAV dump still works
+    let mut audio = if opt_headless {
+        None
+    } else {
+        match Audio::new() {
+            Ok(audio) => {
+                info!("audio: sample rate: {}", audio.sample_rate());
+                cpu.mmu.apu.set_sample_rate(audio.sample_rate());
+                Some(audio)
+            }
+            Err(e) => {
+                warn!("audio: {}", e);
+                None
+            }
+        }
+    };
+    let mut audio_dump = match matches.value_of("audio-dump") {
+        Some(f) => match AudioDump::new(path::Path::new(f), cpu.mmu.apu.sample_rate()) {
+            Ok(audio_dump) => Some(audio_dump),
+            Err(e) => {
+                error!("audio dump: {} {}", f, e);
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let sync = match matches.value_of("sync") {
+        Some("clock") => SyncMode::Clock,
+        _ if audio.is_none() => SyncMode::Clock,
+        _ => SyncMode::Audio,
+    };
+    let mut pacer = Pacer::new(sync);
+    info!("sync: {:?}", pacer.sync());
+
+    let mut frames: u32 = 0;
+    let mut total_frames: u64 = 0;
     if opt_headless {
         // for debug
-        loop {
-            let now = time::Instant::now();
+        while running(total_frames) {
             let mut elapsed_tick: u32 = 0;
 
             while elapsed_tick < CPU_CYCLES_PER_FRAME {
-                elapsed_tick += cpu.run() as u32;
+                elapsed_tick += run(&mut cpu);
+            }
+
+            output_audio(&mut cpu, &mut audio, &mut audio_dump);
+
+            if let Some(capture) = &capture {
+                let output = capture.take_output();
+                if !output.is_empty() {
+                    let mut stdout = io::stdout();
+                    stdout.write_all(&output).ok();
+                    stdout.flush().ok();
+                }
+            }
+
+            frames += 1;
+            total_frames += 1;
+            if frames >= FRAMES_PER_SAVE {
+                frames = 0;
+                save(&cpu, &mut save_file, &mut audio_dump);
             }
 
-            sleep(now);
+            pacer.wait(&mut audio);
         }
     } else {
         let mut window = Window::new(
@@ -117,12 +464,11 @@ fn main() {
         });
         window.set_position(200, 200);
 
-        while window.is_open() && !window.is_key_down(Key::Escape) {
-            let now = time::Instant::now();
+        while window.is_open() && !window.is_key_down(Key::Escape) && running(total_frames) {
             let mut elapsed_tick: u32 = 0;
 
             while elapsed_tick < CPU_CYCLES_PER_FRAME {
-                elapsed_tick += cpu.run();
+                elapsed_tick += run(&mut cpu);
             }
 
             if cpu.mmu.ppu.is_lcd_on() {
@@ -144,7 +490,20 @@ fn main() {
                 }
             });
 
-            sleep(now);
+            output_audio(&mut cpu, &mut audio, &mut audio_dump);
+
+            frames += 1;
+            total_frames += 1;
+            if frames >= FRAMES_PER_SAVE {
+                frames = 0;
+                save(&cpu, &mut save_file, &mut audio_dump);
+            }
+
+            pacer.wait(&mut audio);
         }
     }
+
+    save(&cpu, &mut save_file, &mut audio_dump);
+    // a pending printout is only saved here
+    cpu.mmu.serial.flush();
 }
@@ -0,0 +1,84 @@
+use gameboy_rs_lib::{Printer, Printout, SerialDevice, PRINTOUT_WIDTH};
+use log::{info, warn};
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+// grayscale of the printer shades, from white to black
+const SHADES: [u8; 4] = [0xff, 0xaa, 0x55, 0x00];
+
+// Game Boy Printer writing each printout to a PNG file in a directory
+#[derive(Debug)]
+pub struct PngPrinter {
+    printer: Printer,
+    dir: PathBuf,
+    count: u32,
+}
+
+impl PngPrinter {
+    pub fn new(dir: &Path) -> io::Result<PngPrinter> {
+        fs::create_dir_all(dir)?;
+        Ok(PngPrinter {
+            printer: Printer::new(),
+            dir: dir.to_path_buf(),
+            count: 0,
+        })
+    }
+
+    // the next file name not taken yet, so earlier printouts are kept
+    fn next_path(&mut self) -> PathBuf {
+        loop {
+            self.count += 1;
+            let p = self.dir.join(format!("printout-{:04}.png", self.count));
+            if !p.exists() {
+                return p;
+            }
+        }
+    }
+
+    fn save_printouts(&mut self) {
+        while let Some(printout) = self.printer.take_printout() {
+            self.save(&printout);
+        }
+    }
+
+    fn save(&mut self, printout: &Printout) {
+        let p = self.next_path();
+        match write_png(&p, printout) {
+            Ok(()) => info!("printer: {} height: {}", p.display(), printout.height),
+            Err(e) => warn!("printer: {} {}", p.display(), e),
+        }
+    }
+}
+
+fn write_png(p: &Path, printout: &Printout) -> Result<(), png::EncodingError> {
+    let file = File::create(p)?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        PRINTOUT_WIDTH as u32,
+        printout.height as u32,
+    );
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let data: Vec<u8> = printout
+        .pixels
+        .iter()
+        .map(|&s| SHADES[s as usize])
+        .collect();
+    encoder.write_header()?.write_image_data(&data)
+}
+
+impl SerialDevice for PngPrinter {
+    fn exchange(&mut self, data: u8) -> u8 {
+        let v = self.printer.exchange(data);
+        self.save_printouts();
+        v
+    }
+
+    // the sheet still in the printer when the emulator exits
+    fn flush(&mut self) {
+        self.printer.flush();
+        self.save_printouts();
+    }
+}
@@ -0,0 +1,71 @@
+use std::{thread, time};
+
+use crate::audio::Audio;
+
+// 70224 ticks at 4194304 Hz, about 59.73 frames per second
+const FRAME_NANOS: u64 = 70224 * 1_000_000_000 / 4194304;
+// the output rate never drifts further than 0.5% from the device rate
+const MAX_RATE_DELTA: f64 = 0.005;
+// gives up waiting for a stalled sound device after this many frames
+const MAX_AUDIO_WAIT_FRAMES: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncMode {
+    // the sound device consumes samples at its own rate and the emulation follows
+    Audio,
+    // sleeps until the next frame is due on the wall clock
+    Clock,
+}
+
+pub struct Pacer {
+    sync: SyncMode,
+    deadline: time::Instant,
+}
+
+impl Pacer {
+    pub fn new(sync: SyncMode) -> Pacer {
+        Pacer {
+            sync,
+            deadline: time::Instant::now(),
+        }
+    }
+
+    pub fn sync(&self) -> SyncMode {
+        self.sync
+    }
+
+    // blocks until the next frame should start; call once per emulated frame
+    pub fn wait(&mut self, audio: &mut Option<Audio>) {
+        match (self.sync, audio) {
+            (SyncMode::Audio, Some(audio)) => self.wait_audio(audio),
+            _ => self.wait_clock(),
+        }
+    }
+
+    fn wait_clock(&mut self) {
+        let frame = time::Duration::from_nanos(FRAME_NANOS);
+        self.deadline += frame;
+        let now = time::Instant::now();
+        if self.deadline > now {
+            thread::sleep(self.deadline - now);
+        } else if now - self.deadline > frame {
+            // too far behind (e.g. the window was dragged); don't try to catch up
+            self.deadline = now;
+        }
+    }
+
+    fn wait_audio(&mut self, audio: &mut Audio) {
+        let target = audio.capacity() / 2;
+        let timeout = time::Duration::from_nanos(FRAME_NANOS * MAX_AUDIO_WAIT_FRAMES as u64);
+        let now = time::Instant::now();
+        while audio.len() > target && now.elapsed() < timeout {
+            thread::sleep(time::Duration::from_millis(1));
+        }
+        self.deadline = time::Instant::now();
+
+        // a nearly empty buffer makes slightly more samples per frame and a full one slightly fewer,
+        // so the fill level settles around the target without audible pitch changes
+        let fill = audio.len().min(audio.capacity()) as f64 / audio.capacity() as f64;
+        audio.set_rate_ratio(1.0 + MAX_RATE_DELTA * (1.0 - 2.0 * fill));
+    }
+}